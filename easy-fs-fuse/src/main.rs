@@ -8,6 +8,9 @@ use std::sync::Mutex;
 /// Use a block size of 512 bytes
 const BLOCK_SZ: usize = 512;
 const BLOCK_NUM: usize = 16384;
+/// Blocks of the swap area of the kernel, which follows the file system on
+/// the image: 8 blocks for each of its 0x8000 pages
+const SWAP_BLOCK_NUM: usize = 0x8000 * 8;
 
 /// Wrapper for turning a File into a BlockDevice
 struct BlockFile(Mutex<File>);
@@ -60,7 +63,7 @@ fn easy_fs_pack() -> std::io::Result<()> {
             .write(true)
             .create(true)
             .open(format!("{}{}", target_path, "fs.img"))?;
-        f.set_len((BLOCK_NUM * BLOCK_SZ) as u64).unwrap();
+        f.set_len(((BLOCK_NUM + SWAP_BLOCK_NUM) * BLOCK_SZ) as u64).unwrap();
         f
     })));
     let efs = EasyFileSystem::create(block_file.clone(), BLOCK_NUM as u32, 1);
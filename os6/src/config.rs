@@ -1,16 +1,34 @@
 //! Constants used in rCore
 
 pub const USER_STACK_SIZE: usize = 4096 * 2;
+/// Largest size the heap of a process may grow to with brk
+pub const USER_HEAP_LIMIT: usize = 0x100_0000;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
+/// Harts the kernel can run on, each of which has its own boot stack
+pub const MAX_HART_NUM: usize = 4;
 pub const KERNEL_HEAP_SIZE: usize = 0x20_0000;
 pub const MEMORY_END: usize = 0x88000000;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 pub const MAX_SYSCALL_NUM: usize = 500;
+pub const MAX_FD_NUM: usize = 1024;
+/// The swap area comes right after the blocks of easy-fs on the block device
+pub const SWAP_START_BLOCK: usize = 16384;
+/// Pages the swap area can hold
+pub const SWAP_SLOT_NUM: usize = 0x8000;
+/// User pages are swapped out when fewer frames than this are free...
+pub const SWAP_LOW_WATERMARK: usize = 256;
+/// ...until this many are
+pub const SWAP_HIGH_WATERMARK: usize = 512;
 
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
-pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
+pub const TRAP_CONTEXT_BASE: usize = TRAMPOLINE - PAGE_SIZE;
 pub const CLOCK_FREQ: usize = 12500000;
 pub const MMIO: &[(usize, usize)] = &[
     (0x10001000, 0x1000),
 ];
+pub const MAX_MAIL_NUM: usize = 16;
+pub const MAX_MAIL_LEN: usize = 256;
+/// Stride scheduling: a thread advances by BIG_STRIDE / priority each time it runs
+pub const BIG_STRIDE: usize = 1 << 20;
+pub const DEFAULT_PRIORITY: usize = 16;
@@ -1,10 +1,14 @@
 //! SBI console driver, for text output
 
 use crate::sbi::console_putchar;
+use crate::sync::SpinNoIrqLock;
 use core::fmt::{self, Write};
 
 struct Stdout;
 
+/// Keeps lines printed by different harts from interleaving
+static PRINT_LOCK: SpinNoIrqLock<()> = SpinNoIrqLock::new(());
+
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         for c in s.chars() {
@@ -15,6 +19,7 @@ impl Write for Stdout {
 }
 
 pub fn print(args: fmt::Arguments) {
+    let _guard = PRINT_LOCK.lock();
     Stdout.write_fmt(args).unwrap();
 }
 
@@ -61,6 +66,7 @@ pub fn print_colorized(
     foreground_color: impl Into<u8>,
     background_color: impl Into<u8>,
 ) {
+    let _guard = PRINT_LOCK.lock();
     Stdout
         .write_fmt(colorize!(args, foreground_color, background_color))
         .unwrap();
@@ -12,29 +12,27 @@ use crate::mm::{
     kernel_token,
 };
 use super::BlockDevice;
-use crate::sync::UPSafeCell;
+use crate::sync::SpinNoIrqLock;
 use alloc::vec::Vec;
 use lazy_static::*;
 
 #[allow(unused)]
 const VIRTIO0: usize = 0x10001000;
 
-pub struct VirtIOBlock(UPSafeCell<VirtIOBlk<'static>>);
+pub struct VirtIOBlock(SpinNoIrqLock<VirtIOBlk<'static>>);
 
 lazy_static! {
-    static ref QUEUE_FRAMES: UPSafeCell<Vec<FrameTracker>> = unsafe { 
-        UPSafeCell::new(Vec::new())
-    };
+    static ref QUEUE_FRAMES: SpinNoIrqLock<Vec<FrameTracker>> = SpinNoIrqLock::new(Vec::new());
 }
 
 impl BlockDevice for VirtIOBlock {
     fn read_block(&self, block_id: usize, buf: &mut [u8]) {
-        self.0.exclusive_access()
+        self.0.lock()
         .read_block(block_id, buf)
         .expect("Error when reading VirtIOBlk");
     }
     fn write_block(&self, block_id: usize, buf: &[u8]) {
-        self.0.exclusive_access()
+        self.0.lock()
         .write_block(block_id, buf)
         .expect("Error when writing VirtIOBlk");
     }
@@ -44,7 +42,7 @@ impl VirtIOBlock {
     #[allow(unused)]
     pub fn new() -> Self {
         unsafe {
-            Self(UPSafeCell::new(VirtIOBlk::new(
+            Self(SpinNoIrqLock::new(VirtIOBlk::new(
                 &mut *(VIRTIO0 as *mut VirtIOHeader)
             ).unwrap()))
         }
@@ -58,7 +56,7 @@ pub extern "C" fn virtio_dma_alloc(pages: usize) -> PhysAddr {
         let frame = frame_alloc().unwrap();
         if i == 0 { ppn_base = frame.ppn; }
         assert_eq!(frame.ppn.0, ppn_base.0 + i);
-        QUEUE_FRAMES.exclusive_access().push(frame);
+        QUEUE_FRAMES.lock().push(frame);
     }
     ppn_base.into()
 }
@@ -1,12 +1,20 @@
     .section .text.entry
     .globl _start
 _start:
-    la sp, boot_stack_top
+    # every hart enters here with its hart id in a0, keep it in tp
+    mv tp, a0
+    # boot stack of hart i is the (i+1)-th 64 KiB block of boot_stack
+    addi t0, a0, 1
+    li t1, 4096 * 16
+    mul t0, t0, t1
+    la sp, boot_stack
+    add sp, sp, t0
     call rust_main
 
     .section .bss.stack
     .globl boot_stack
 boot_stack:
-    .space 4096 * 16
+    # 4096 * 16 for each of MAX_HART_NUM harts
+    .space 4096 * 16 * 4
     .globl boot_stack_top
 boot_stack_top:
\ No newline at end of file
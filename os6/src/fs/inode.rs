@@ -3,7 +3,7 @@ use easy_fs::{
     Inode,
 };
 use crate::drivers::BLOCK_DEVICE;
-use crate::sync::UPSafeCell;
+use crate::sync::SpinNoIrqLock;
 use alloc::sync::Arc;
 use lazy_static::*;
 use bitflags::*;
@@ -16,10 +16,10 @@ use crate::mm::UserBuffer;
 pub struct OSInode {
     readable: bool,
     writable: bool,
-    inner: UPSafeCell<OSInodeInner>,
+    inner: SpinNoIrqLock<OSInodeInner>,
 }
 
-/// The OS inode inner in 'UPSafeCell'
+/// The OS inode inner in 'SpinNoIrqLock'
 pub struct OSInodeInner {
     offset: usize,
     inode: Arc<Inode>,
@@ -35,15 +35,15 @@ impl OSInode {
         Self {
             readable,
             writable,
-            inner: unsafe { UPSafeCell::new(OSInodeInner {
+            inner: SpinNoIrqLock::new(OSInodeInner {
                 offset: 0,
                 inode,
-            })},
+            }),
         }
     }
     /// Read all data inside a inode into vector
     pub fn read_all(&self) -> Vec<u8> {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner.lock();
         let mut buffer = [0u8; 512];
         let mut v: Vec<u8> = Vec::new();
         loop {
@@ -60,7 +60,7 @@ impl OSInode {
     // // 似乎只有学习类似`read`或者`write`的操作，生成一个全新的trait类型，并在 `Stdin` and `Stdout`中实现，才能使之被正常访问。
     // #[allow(dead_code)]
     // fn fstat(&self) -> (u64, StatMode, u32) {
-    //     let inner = self.inner.exclusive_access();     // OSInode
+    //     let inner = self.inner.lock();     // OSInode
     //     let inode = &inner.inode;                               // Inode
     //     let (ino, dir_add, nlink) = ROOT_INODE.fstat(inode);
     //     let mode = match dir_add {
@@ -162,7 +162,7 @@ impl File for OSInode {
 
     // fn fstat(&self) -> u64 {
         // println!("A");
-        let inner = self.inner.exclusive_access();     // OSInode
+        let inner = self.inner.lock();     // OSInode
         // println!("B");
         let inode = &inner.inode;                               // Inode
         // println!("C");
@@ -182,8 +182,11 @@ impl File for OSInode {
     }
     fn readable(&self) -> bool { self.readable }
     fn writable(&self) -> bool { self.writable }
+    fn inode(&self) -> Option<Arc<Inode>> {
+        Some(Arc::clone(&self.inner.lock().inode))
+    }
     fn read(&self, mut buf: UserBuffer) -> usize {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner.lock();
         let mut total_read_size = 0usize;
         for slice in buf.buffers.iter_mut() {
             let read_size = inner.inode.read_at(inner.offset, *slice);
@@ -196,7 +199,7 @@ impl File for OSInode {
         total_read_size
     }
     fn write(&self, buf: UserBuffer) -> usize {
-        let mut inner = self.inner.exclusive_access();
+        let mut inner = self.inner.lock();
         let mut total_write_size = 0usize;
         for slice in buf.buffers.iter() {
             let write_size = inner.inode.write_at(inner.offset, *slice);
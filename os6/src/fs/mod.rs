@@ -1,7 +1,10 @@
 mod stdio;
 mod inode;
+mod pipe;
 
 use crate::mm::UserBuffer;
+use alloc::sync::Arc;
+use easy_fs::Inode;
 
 // QUESTION: 这个地方应该理解成为 File trait 需要同时 Send and Sync 特征？
 /// The common abstraction of all IO resources
@@ -12,6 +15,10 @@ pub trait File : Send + Sync {
     fn write(&self, buf: UserBuffer) -> usize;
     fn fstat(&self) -> (u64, StatMode, u32);
     // fn fstat(&self) -> u64;
+    /// The easy-fs inode behind the file, for mmap; None if there is none
+    fn inode(&self) -> Option<Arc<Inode>> {
+        None
+    }
 }
 
 /// The stat of a inode
@@ -42,5 +49,8 @@ bitflags! {
     }
 }    
 
-pub use stdio::{Stdin, Stdout};
+pub use stdio::{
+    poll_console, set_tty_foreground_pgid, tty_foreground_pgid, tty_has_readers, Stdin, Stdout,
+};
 pub use inode::{OSInode, open_file, OpenFlags, list_apps, linkat, unlinkat};
+pub use pipe::{make_pipe, Pipe};
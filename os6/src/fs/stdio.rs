@@ -2,7 +2,95 @@ use super::File;
 use crate::fs::StatMode;
 use crate::mm::{UserBuffer};
 use crate::sbi::console_getchar;
-use crate::task::suspend_current_and_run_next;
+use crate::sync::{SpinNoIrqLock, WaitQueue};
+use crate::task::{
+    block_current_and_run_next, current_process, current_signal_pending, send_group_signal,
+    SignalFlags,
+};
+use alloc::collections::VecDeque;
+use lazy_static::*;
+
+/// ^C, interrupting the foreground process group
+const CTRL_C: u8 = 0x03;
+/// ^Z, stopping the foreground process group
+const CTRL_Z: u8 = 0x1a;
+
+/// Line discipline of the console
+///
+/// Input is polled from SBI on timer interrupts, so that ^C and ^Z are
+/// noticed even when nobody reads from the console. They turn into SIGINT
+/// and SIGTSTP for the foreground process group, everything else is queued
+/// for [`Stdin`].
+pub struct Tty {
+    /// Characters typed but not read yet
+    input: VecDeque<u8>,
+    /// Process group which may read from the console and gets its signals
+    foreground_pgid: usize,
+    /// Threads blocked in reading
+    readers: WaitQueue,
+}
+
+lazy_static! {
+    static ref TTY: SpinNoIrqLock<Tty> = SpinNoIrqLock::new(Tty {
+        input: VecDeque::new(),
+        // the group of initproc, which every process is in until setpgid
+        foreground_pgid: 0,
+        readers: WaitQueue::new(),
+    });
+}
+
+/// Take the characters typed on the console since last time
+pub fn poll_console() {
+    let mut signals = SignalFlags::empty();
+    let mut tty = TTY.lock();
+    loop {
+        // SBI returns usize::MAX if there is nothing, some versions 0
+        let c = console_getchar();
+        if c == 0 || c == usize::MAX {
+            break;
+        }
+        match c as u8 {
+            CTRL_C => {
+                print!("^C");
+                signals |= SignalFlags::SIGINT;
+            }
+            CTRL_Z => {
+                print!("^Z");
+                signals |= SignalFlags::SIGTSTP;
+            }
+            ch => tty.input.push_back(ch),
+        }
+    }
+    if tty.input.is_empty() && signals.is_empty() {
+        return;
+    }
+    let foreground_pgid = tty.foreground_pgid;
+    drop(tty);
+    // readers in the foreground group have to leave for the signals to be
+    // delivered, which they check after locking TTY
+    for signal in [SignalFlags::SIGINT, SignalFlags::SIGTSTP] {
+        if signals.contains(signal) {
+            send_group_signal(foreground_pgid, signal);
+        }
+    }
+    TTY.lock().readers.wake_all();
+}
+
+/// Whether some thread is blocked until a character is typed, in which case
+/// the console has to be polled even on an idle hart
+pub fn tty_has_readers() -> bool {
+    !TTY.lock().readers.is_empty()
+}
+
+/// The foreground process group of the console
+pub fn tty_foreground_pgid() -> usize {
+    TTY.lock().foreground_pgid
+}
+
+/// Make `pgid` the foreground process group of the console
+pub fn set_tty_foreground_pgid(pgid: usize) {
+    TTY.lock().foreground_pgid = pgid;
+}
 
 /// The standard input
 pub struct Stdin;
@@ -19,20 +107,35 @@ impl File for Stdin {
     // }
     fn readable(&self) -> bool { true }
     fn writable(&self) -> bool { false }
+    /// Read one character, blocking until it is typed
+    ///
+    /// Return 0 if a signal interrupts the read, or if the process is not in
+    /// the foreground group: SIGTTIN is sent to its group then.
     fn read(&self, mut user_buf: UserBuffer) -> usize {
         assert_eq!(user_buf.len(), 1);
-        // busy loop
-        let mut c: usize;
-        loop {
-            c = console_getchar();
-            if c == 0 {
-                suspend_current_and_run_next();
-                continue;
-            } else {
-                break;
+        let pgid = current_process().inner_exclusive_access().pgid;
+        let ch = loop {
+            let mut tty = TTY.lock();
+            if tty.foreground_pgid != pgid {
+                drop(tty);
+                send_group_signal(pgid, SignalFlags::SIGTTIN);
+                return 0;
             }
-        }
-        let ch = c as u8;
+            // checked under the lock, so that a ^C cannot slip in before we
+            // block and find nobody to wake up
+            if current_signal_pending() {
+                return 0;
+            }
+            if let Some(ch) = tty.input.pop_front() {
+                break ch;
+            }
+            tty.readers.add_current_interruptible();
+            drop(tty);
+            if !block_current_and_run_next() {
+                TTY.lock().readers.remove_current();
+                return 0;
+            }
+        };
         unsafe { user_buf.buffers[0].as_mut_ptr().write_volatile(ch); }
         1
     }
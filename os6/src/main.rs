@@ -12,8 +12,10 @@
 //! initialize various pieces of functionality. (See its source code for
 //! details.)
 //!
-//! We then call [`task::run_first_task()`] and for the first time go to
-//! userspace.
+//! We then call [`task::run_tasks()`] and for the first time go to
+//! userspace. The first hart to arrive does the initialization and then
+//! starts the others, which only set up their own paging, trap entry and
+//! timer before joining the scheduling loop.
 
 #![no_std]
 #![no_main]
@@ -27,6 +29,9 @@ extern crate log;
 
 extern crate alloc;
 
+use config::MAX_HART_NUM;
+use core::sync::atomic::{AtomicBool, Ordering};
+
 #[macro_use]
 mod console;
 mod config;
@@ -56,19 +61,52 @@ fn clear_bss() {
     }
 }
 
+/// Cleared by the first hart to enter the kernel, which then does the
+/// initialization. It is placed in .data so that clear_bss leaves it alone.
+static FIRST_HART: AtomicBool = AtomicBool::new(true);
+/// Set once the first hart is done with the initialization
+static BOOT_DONE: AtomicBool = AtomicBool::new(false);
+
+/// start the other harts at `_start` through SBI HSM
+fn start_other_harts(boot_hart_id: usize) {
+    extern "C" {
+        fn _start();
+    }
+    for hart_id in (0..MAX_HART_NUM).filter(|id| *id != boot_hart_id) {
+        // harts that do not exist, or have been started by the SBI already,
+        // just make it fail
+        sbi::hart_start(hart_id, _start as usize, 0);
+    }
+}
+
 #[no_mangle]
-/// the rust entry-point of os
-pub fn rust_main() -> ! {
-    clear_bss();
-    logging::init();
-    println!("[kernel] Hello, world!");
-    mm::init();
-    mm::remap_test();
-    trap::init();
-    trap::enable_timer_interrupt();
-    timer::set_next_trigger();
-    fs::list_apps();
-    task::add_initproc();
+/// the rust entry-point of os, entered by every hart
+pub fn rust_main(hart_id: usize) -> ! {
+    if FIRST_HART.swap(false, Ordering::AcqRel) {
+        clear_bss();
+        logging::init();
+        println!("[kernel] Hello, world!");
+        mm::init();
+        mm::remap_test();
+        trap::init();
+        trap::enable_timer_interrupt();
+        trap::enable_software_interrupt();
+        timer::set_next_trigger();
+        fs::list_apps();
+        task::add_initproc();
+        BOOT_DONE.store(true, Ordering::Release);
+        start_other_harts(hart_id);
+    } else {
+        while !BOOT_DONE.load(Ordering::Acquire) {
+            core::hint::spin_loop();
+        }
+        mm::init_other_hart();
+        trap::init();
+        trap::enable_timer_interrupt();
+        trap::enable_software_interrupt();
+        timer::set_next_trigger();
+        println!("[kernel] hart {} is up", hart_id);
+    }
     task::run_tasks();
     panic!("Unreachable in rust_main!");
 }
@@ -1,9 +1,15 @@
 //! Implementation of [`FrameAllocator`] which
 //! controls all the frames in the operating system.
+//!
+//! A frame may be shared, e.g. by processes after a copy-on-write fork, so
+//! the allocator keeps a reference count for each frame. Every
+//! [`FrameTracker`] holds one reference, and the frame is recycled once the
+//! last one is dropped.
 
 use super::{PhysAddr, PhysPageNum};
 use crate::config::MEMORY_END;
-use crate::sync::UPSafeCell;
+use crate::sync::SpinNoIrqLock;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::fmt::{self, Debug, Formatter};
 use lazy_static::*;
@@ -22,6 +28,28 @@ impl FrameTracker {
         }
         Self { ppn }
     }
+    /// Another reference to the same frame
+    pub fn share(&self) -> Self {
+        Self::from_ppn(self.ppn)
+    }
+    /// Another reference to the frame `ppn`, which must be allocated
+    pub fn from_ppn(ppn: PhysPageNum) -> Self {
+        FRAME_ALLOCATOR.lock().add_ref(ppn);
+        Self { ppn }
+    }
+    /// Another reference to the frame `ppn`, None if it is not an allocated
+    /// frame, e.g. a page of the kernel image
+    pub fn try_from_ppn(ppn: PhysPageNum) -> Option<Self> {
+        if FRAME_ALLOCATOR.lock().try_add_ref(ppn) {
+            Some(Self { ppn })
+        } else {
+            None
+        }
+    }
+    /// Number of references to the frame, this one included
+    pub fn ref_count(&self) -> usize {
+        FRAME_ALLOCATOR.lock().ref_count(self.ppn)
+    }
 }
 
 impl Debug for FrameTracker {
@@ -38,50 +66,96 @@ impl Drop for FrameTracker {
 
 trait FrameAllocator {
     fn new() -> Self;
+    /// Allocate a frame with a single reference
     fn alloc(&mut self) -> Option<PhysPageNum>;
+    /// Drop a reference, recycling the frame if it was the last one
     fn dealloc(&mut self, ppn: PhysPageNum);
+    fn add_ref(&mut self, ppn: PhysPageNum);
+    /// Add a reference if `ppn` is an allocated frame, return false if not
+    fn try_add_ref(&mut self, ppn: PhysPageNum) -> bool;
+    fn ref_count(&self, ppn: PhysPageNum) -> usize;
+    /// Number of frames left to allocate
+    fn free_count(&self) -> usize;
 }
 
 /// an implementation for frame allocator
 pub struct StackFrameAllocator {
+    /// First frame under management
+    start: usize,
     current: usize,
     end: usize,
     recycled: Vec<usize>,
+    /// Reference counts indexed by ppn - start, 0 for free frames
+    ref_counts: Vec<u16>,
 }
 
 impl StackFrameAllocator {
     pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
+        self.start = l.0;
         self.current = l.0;
         self.end = r.0;
+        self.ref_counts = vec![0; r.0 - l.0];
         info!("last {} Physical Frames.", self.end - self.current);
     }
+    fn ref_count_mut(&mut self, ppn: PhysPageNum) -> &mut u16 {
+        let ref_count = &mut self.ref_counts[ppn.0 - self.start];
+        // validity check
+        if *ref_count == 0 {
+            panic!("Frame ppn={:#x} has not been allocated!", ppn.0);
+        }
+        ref_count
+    }
 }
 impl FrameAllocator for StackFrameAllocator {
     fn new() -> Self {
         Self {
+            start: 0,
             current: 0,
             end: 0,
             recycled: Vec::new(),
+            ref_counts: Vec::new(),
         }
     }
     fn alloc(&mut self) -> Option<PhysPageNum> {
-        if let Some(ppn) = self.recycled.pop() {
-            Some(ppn.into())
+        let ppn = if let Some(ppn) = self.recycled.pop() {
+            ppn
         } else if self.current == self.end {
-            None
+            return None;
         } else {
             self.current += 1;
-            Some((self.current - 1).into())
-        }
+            self.current - 1
+        };
+        self.ref_counts[ppn - self.start] = 1;
+        Some(ppn.into())
     }
     fn dealloc(&mut self, ppn: PhysPageNum) {
-        let ppn = ppn.0;
-        // validity check
-        if ppn >= self.current || self.recycled.iter().any(|v| *v == ppn) {
-            panic!("Frame ppn={:#x} has not been allocated!", ppn);
+        let ref_count = self.ref_count_mut(ppn);
+        *ref_count -= 1;
+        if *ref_count == 0 {
+            // recycle
+            self.recycled.push(ppn.0);
         }
-        // recycle
-        self.recycled.push(ppn);
+    }
+    fn add_ref(&mut self, ppn: PhysPageNum) {
+        *self.ref_count_mut(ppn) += 1;
+    }
+    fn try_add_ref(&mut self, ppn: PhysPageNum) -> bool {
+        if ppn.0 < self.start || ppn.0 >= self.current {
+            return false;
+        }
+        match &mut self.ref_counts[ppn.0 - self.start] {
+            0 => false,
+            ref_count => {
+                *ref_count += 1;
+                true
+            }
+        }
+    }
+    fn ref_count(&self, ppn: PhysPageNum) -> usize {
+        self.ref_counts[ppn.0 - self.start] as usize
+    }
+    fn free_count(&self) -> usize {
+        self.end - self.current + self.recycled.len()
     }
 }
 
@@ -89,15 +163,15 @@ type FrameAllocatorImpl = StackFrameAllocator;
 
 lazy_static! {
     /// frame allocator instance through lazy_static!
-    pub static ref FRAME_ALLOCATOR: UPSafeCell<FrameAllocatorImpl> =
-        unsafe { UPSafeCell::new(FrameAllocatorImpl::new()) };
+    pub static ref FRAME_ALLOCATOR: SpinNoIrqLock<FrameAllocatorImpl> =
+        SpinNoIrqLock::new(FrameAllocatorImpl::new());
 }
 
 pub fn init_frame_allocator() {
     extern "C" {
         fn ekernel();
     }
-    FRAME_ALLOCATOR.exclusive_access().init(
+    FRAME_ALLOCATOR.lock().init(
         PhysAddr::from(ekernel as usize).ceil(),
         PhysAddr::from(MEMORY_END).floor(),
     );
@@ -106,14 +180,19 @@ pub fn init_frame_allocator() {
 /// initiate the frame allocator using `ekernel` and `MEMORY_END`
 pub fn frame_alloc() -> Option<FrameTracker> {
     FRAME_ALLOCATOR
-        .exclusive_access()
+        .lock()
         .alloc()
         .map(FrameTracker::new)
 }
 
-/// deallocate a frame
+/// Number of frames left to allocate
+pub fn free_frame_count() -> usize {
+    FRAME_ALLOCATOR.lock().free_count()
+}
+
+/// drop a reference to a frame, deallocating it if it was the last one
 pub fn frame_dealloc(ppn: PhysPageNum) {
-    FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
+    FRAME_ALLOCATOR.lock().dealloc(ppn);
 }
 
 #[allow(unused)]
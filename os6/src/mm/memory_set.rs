@@ -1,14 +1,16 @@
 //! Implementation of [`MapArea`] and [`MemorySet`].
 
+use super::swap::{swap_alloc, swap_dup, swap_free, swap_read, swap_ref_count, swap_write};
 use super::{frame_alloc, FrameTracker};
 use super::{PTEFlags, PageTable, PageTableEntry};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
-use crate::config::{MEMORY_END, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE, MMIO};
-use crate::sync::UPSafeCell;
+use crate::config::{MEMORY_END, PAGE_SIZE, TRAMPOLINE, MMIO, USER_HEAP_LIMIT};
+use crate::sync::SpinNoIrqLock;
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use easy_fs::Inode;
 use lazy_static::*;
 use riscv::register::satp;
 
@@ -27,65 +29,62 @@ extern "C" {
 
 lazy_static! {
     /// a memory set instance through lazy_static! managing kernel space
-    pub static ref KERNEL_SPACE: Arc<UPSafeCell<MemorySet>> =
-        Arc::new(unsafe { UPSafeCell::new(MemorySet::new_kernel()) });
+    pub static ref KERNEL_SPACE: Arc<SpinNoIrqLock<MemorySet>> =
+        Arc::new(SpinNoIrqLock::new(MemorySet::new_kernel()));
 }
 
 /// Get the token of the kernel memory space
 pub fn kernel_token() -> usize {
-    KERNEL_SPACE.exclusive_access().token()
+    KERNEL_SPACE.lock().token()
 }
 
 /// memory set structure, controls virtual-memory space
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    /// Start of the heap area, right after the elf segments
+    heap_bottom: usize,
+    /// Program break, where the heap area ends
+    brk: usize,
 }
 
 impl MemorySet {
 
+    /// Whether `vpn` is in some area, even if no frame is mapped there yet
     pub fn find_vpn(&self, vpn: VirtPageNum) -> bool {
-
-        let pte = self.translate(vpn);
-
-        match pte {
-            Some(x) => {
-                if x.is_valid() { return true }
-                else { return false }
-            }
-            None => false
-        }
-
-        // for map_area in self.areas.iter() {
-        //     if map_area.has_vpn(vpn) {
-        //         return true;
-        //     }
-        // }
-        // return false;
-
-        // for map_area in self.areas.iter() {
-        //     if map_area.find_vpn(vpn) {
-        //         return true;
-        //     }
-        // }
-        // false
+        self.areas.iter().any(|area| area.has_vpn(vpn))
     }
 
-    pub fn delete_pte_from(&mut self, vpn: VirtPageNum) {
-        // println!("===================================");
-        for map_area in self.areas.iter_mut() {
-            if map_area.has_vpn(vpn) {
-                // print!("======= delete succeed~ ====== ");
-                map_area.unmap_one(&mut self.page_table, vpn);
+    /// Take the pages in `[start_vpn, end_vpn)` away from the areas holding
+    /// them, which are shrunk or split in two
+    pub fn remove_range(&mut self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) {
+        let mut areas = Vec::new();
+        for mut area in core::mem::take(&mut self.areas) {
+            let (area_start, area_end) = (area.vpn_range.get_start(), area.vpn_range.get_end());
+            if area_end <= start_vpn || end_vpn <= area_start {
+                areas.push(area);
+                continue;
             }
+            let tail = (end_vpn < area_end).then(|| area.split_off(end_vpn));
+            let mut removed = if start_vpn > area_start {
+                let removed = area.split_off(start_vpn);
+                areas.push(area);
+                removed
+            } else {
+                area
+            };
+            removed.unmap(&mut self.page_table);
+            areas.extend(tail);
         }
-
+        self.areas = areas;
     }
 
     pub fn new_bare() -> Self {
         Self {
             page_table: PageTable::new(),
             areas: Vec::new(),
+            heap_bottom: 0,
+            brk: 0,
         }
     }
     pub fn token(&self) -> usize {
@@ -103,6 +102,28 @@ impl MemorySet {
             None,
         );
     }
+    /// Map `file` at `[start_va, end_va)`, whose pages are read from it on
+    /// the first access. Assume that no conflicts.
+    pub fn insert_file_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+        file: FileMapping,
+    ) {
+        let mut map_area = MapArea::new(start_va, end_va, MapType::Framed, permission);
+        map_area.file = Some(file);
+        self.push(map_area, None);
+    }
+    /// Write the dirty pages of shared file mappings in `[start_vpn,
+    /// end_vpn)` back to their files
+    pub fn sync_range(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) {
+        for area in self.areas.iter() {
+            for (vpn, _) in area.data_frames.range(start_vpn..end_vpn) {
+                area.write_back(&self.page_table, *vpn);
+            }
+        }
+    }
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
         if let Some((idx, area)) = self
             .areas
@@ -205,8 +226,14 @@ impl MemorySet {
         }
         memory_set
     }
-    /// Include sections in elf and trampoline and TrapContext and user stack,
-    /// also returns user_sp and entry point.
+    /// Include sections in elf and trampoline,
+    /// also returns the base of user stacks and entry point.
+    ///
+    /// An empty heap area follows the elf segments, and the user stacks come
+    /// after the room it may grow into.
+    ///
+    /// User stacks and trap contexts are per thread, so they are mapped
+    /// later on by the threads themselves.
     pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
         let mut memory_set = Self::new_bare();
         // map trampoline
@@ -242,44 +269,56 @@ impl MemorySet {
                 );
             }
         }
-        // map user stack with U flags
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.into();
-        // guard page
-        user_stack_bottom += PAGE_SIZE;
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
+        let heap_bottom: usize = max_end_va.into();
         memory_set.push(
             MapArea::new(
-                user_stack_bottom.into(),
-                user_stack_top.into(),
+                heap_bottom.into(),
+                heap_bottom.into(),
                 MapType::Framed,
                 MapPermission::R | MapPermission::W | MapPermission::U,
             ),
             None,
         );
-        // map TrapContext
-        memory_set.push(
-            MapArea::new(
-                TRAP_CONTEXT.into(),
-                TRAMPOLINE.into(),
-                MapType::Framed,
-                MapPermission::R | MapPermission::W,
-            ),
-            None,
-        );
+        memory_set.heap_bottom = heap_bottom;
+        memory_set.brk = heap_bottom;
+        let mut user_stack_base: usize = heap_bottom + USER_HEAP_LIMIT;
+        // guard page
+        user_stack_base += PAGE_SIZE;
         (
             memory_set,
-            user_stack_top,
+            user_stack_base,
             elf.header.pt2.entry_point() as usize,
         )
     }
     /// Copy an identical user_space
-    pub fn from_existed_user(user_space: &MemorySet) -> MemorySet {
+    ///
+    /// Pages accessible to the user are not copied but shared copy-on-write:
+    /// they turn read-only in both spaces until either writes to them. Pages
+    /// of shared file mappings stay shared for good. Trap contexts are copied
+    /// right away, as the kernel writes to them through their frames.
+    pub fn from_existed_user(user_space: &mut MemorySet) -> MemorySet {
         let mut memory_set = Self::new_bare();
+        memory_set.heap_bottom = user_space.heap_bottom;
+        memory_set.brk = user_space.brk;
         // map trampoline
         memory_set.map_trampoline();
-        // copy data sections/trap_context/user_stack
+        // share data sections/user_stacks/heap
+        for area in user_space.areas.iter_mut() {
+            if area.is_shared_file() {
+                let new_area = area.share(&mut memory_set.page_table);
+                memory_set.areas.push(new_area);
+            } else if area.map_perm.contains(MapPermission::U) {
+                let new_area =
+                    area.share_cow(&mut user_space.page_table, &mut memory_set.page_table);
+                memory_set.areas.push(new_area);
+            }
+        }
+        // copy trap_contexts
         for area in user_space.areas.iter() {
+            if area.map_perm.contains(MapPermission::U) {
+                continue;
+            }
             let new_area = MapArea::from_another(area);
             memory_set.push(new_area, None);
             // copy data from another space
@@ -293,6 +332,129 @@ impl MemorySet {
         }
         memory_set
     }
+    /// The current program break
+    pub fn brk(&self) -> usize {
+        self.brk
+    }
+    /// Move the program break to `new_brk`, growing or shrinking the heap
+    /// area, whose pages past the break are unmapped. Return false if the
+    /// heap would leave its room or run into another area.
+    pub fn set_brk(&mut self, new_brk: usize) -> bool {
+        if new_brk < self.heap_bottom || new_brk > self.heap_bottom + USER_HEAP_LIMIT {
+            return false;
+        }
+        let heap_start_vpn = VirtAddr::from(self.heap_bottom).floor();
+        let new_end_vpn = VirtAddr::from(new_brk).ceil();
+        // pushed right after the elf segments, so found before an mmap area
+        // which may start at the same page
+        let heap_idx = match self
+            .areas
+            .iter()
+            .position(|area| area.vpn_range.get_start() == heap_start_vpn)
+        {
+            Some(heap_idx) => heap_idx,
+            // unmapped by munmap
+            None => return false,
+        };
+        let old_end_vpn = self.areas[heap_idx].vpn_range.get_end();
+        if new_end_vpn > old_end_vpn {
+            let occupied = VPNRange::new(old_end_vpn, new_end_vpn)
+                .into_iter()
+                .any(|vpn| self.find_vpn(vpn));
+            if occupied {
+                return false;
+            }
+            self.areas[heap_idx].append_to(&mut self.page_table, new_end_vpn);
+        } else if new_end_vpn < old_end_vpn {
+            self.areas[heap_idx].shrink_to(&mut self.page_table, new_end_vpn);
+        }
+        self.brk = new_brk;
+        true
+    }
+    /// Handle a page fault of the user at `vpn`, caused by an access of
+    /// `kind`
+    ///
+    /// The first access to a page of a lazy area maps a zeroed frame there,
+    /// a page swapped out is read back in, and a write to a page shared by a
+    /// copy-on-write fork gets a copy of it. Nothing changes if there is no
+    /// frame for that, so the fault can be handled again once some are freed.
+    pub fn handle_page_fault(
+        &mut self,
+        vpn: VirtPageNum,
+        kind: PageFaultKind,
+    ) -> Result<(), PageFaultError> {
+        let area = match self.areas.iter_mut().find(|area| area.has_vpn(vpn)) {
+            Some(area) => area,
+            None => return Err(PageFaultError::Denied),
+        };
+        let needed = match kind {
+            PageFaultKind::Load => MapPermission::R,
+            // W without R is reserved in SV39, so the MMU faults on it anyway
+            PageFaultKind::Store => MapPermission::R | MapPermission::W,
+            PageFaultKind::Fetch => MapPermission::X,
+        };
+        if !area.map_perm.contains(MapPermission::U | needed) {
+            return Err(PageFaultError::Denied);
+        }
+        let write = kind == PageFaultKind::Store;
+        let mapped = match self.page_table.translate(vpn) {
+            Some(pte) if pte.is_valid() => {
+                // unless a write to a page shared copy-on-write, a stale TLB
+                // entry, flushed on the way back to user, or the kernel about
+                // to access the page
+                !write || pte.writable() || area.copy_on_write(&mut self.page_table, vpn)
+            }
+            pte => match pte.and_then(|pte| pte.swap_slot()) {
+                Some(slot) => area.swap_in(&mut self.page_table, vpn, slot),
+                None => area.try_map_one(&mut self.page_table, vpn),
+            },
+        };
+        if !mapped {
+            return Err(PageFaultError::NoFrame);
+        }
+        // the kernel accesses user pages through their frames, in which case
+        // the MMU does not set A and D for the clock of the swapper
+        let mut flags = self.page_table.translate(vpn).unwrap().flags() | PTEFlags::A;
+        if write {
+            flags |= PTEFlags::D;
+        }
+        self.page_table.set_flags(vpn, flags);
+        Ok(())
+    }
+    /// Go on with the clock hand from `from` over the resident user pages,
+    /// and swap out at most `count` of them. Return the page to go on from
+    /// next time, or None if the hand went past the last one.
+    ///
+    /// See [`MapArea::swap_out_one`] for which pages are taken.
+    pub fn swap_out(
+        &mut self,
+        from: VirtPageNum,
+        count: usize,
+        take_dirty: bool,
+    ) -> Option<VirtPageNum> {
+        let mut pages: Vec<(VirtPageNum, usize)> = self
+            .areas
+            .iter()
+            .enumerate()
+            .filter(|(_, area)| area.is_lazy())
+            .flat_map(|(idx, area)| {
+                area.data_frames
+                    .range(from..)
+                    .map(move |(vpn, _)| (*vpn, idx))
+            })
+            .collect();
+        pages.sort_unstable_by_key(|(vpn, _)| *vpn);
+        let mut swapped = 0;
+        for (vpn, idx) in pages {
+            if swapped == count {
+                return Some(vpn);
+            }
+            if self.areas[idx].swap_out_one(&mut self.page_table, vpn, take_dirty) {
+                swapped += 1;
+            }
+        }
+        None
+    }
     pub fn activate(&self) {
         let satp = self.page_table.token();
         unsafe {
@@ -303,16 +465,35 @@ impl MemorySet {
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.page_table.translate(vpn)
     }
+    /// Unmap all areas, which gives their swap slots back as well, and
+    /// writes shared file mappings back
     pub fn recycle_data_pages(&mut self) {
         //*self = Self::new_bare();
+        for area in self.areas.iter_mut() {
+            area.unmap(&mut self.page_table);
+        }
         self.areas.clear();
     }
 }
 
+impl Drop for MemorySet {
+    /// Recycle the data pages as an exiting process does, e.g. when exec
+    /// replaces the address space: swap slots are freed and shared file
+    /// mappings are written back
+    fn drop(&mut self) {
+        self.recycle_data_pages();
+    }
+}
+
 /// map area structure, controls a contiguous piece of virtual memory
 pub struct MapArea {
     vpn_range: VPNRange,
     data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    /// Slots still holding resident pages as they were swapped in, which
+    /// need not be written out again unless the pages get dirty
+    swap_copies: BTreeMap<VirtPageNum, usize>,
+    /// The file mapped by the area, if it is not anonymous
+    file: Option<FileMapping>,
     map_type: MapType,
     map_perm: MapPermission,
 }
@@ -320,7 +501,25 @@ pub struct MapArea {
 impl MapArea {
 
     pub fn has_vpn(&self, vpn: VirtPageNum) -> bool {
-        return self.vpn_range.get_start() <= vpn && vpn <= self.vpn_range.get_end();
+        self.vpn_range.get_start() <= vpn && vpn < self.vpn_range.get_end()
+    }
+    /// Whether frames are only mapped on the first access to each page,
+    /// which is the case for the areas of the user. The kernel writes to
+    /// trap contexts through their frames, so they are mapped at once.
+    fn is_lazy(&self) -> bool {
+        self.map_type == MapType::Framed && self.map_perm.contains(MapPermission::U)
+    }
+    /// Cut the area in two at `at`, and return the upper part
+    pub fn split_off(&mut self, at: VirtPageNum) -> Self {
+        let mut upper = Self::from_another(self);
+        upper.vpn_range = VPNRange::new(at, self.vpn_range.get_end());
+        upper.data_frames = self.data_frames.split_off(&at);
+        upper.swap_copies = self.swap_copies.split_off(&at);
+        if let Some(file) = upper.file.as_mut() {
+            file.offset += (at.0 - self.vpn_range.get_start().0) * PAGE_SIZE;
+        }
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), at);
+        upper
     }
     pub fn new(
         start_va: VirtAddr,
@@ -333,6 +532,8 @@ impl MapArea {
         Self {
             vpn_range: VPNRange::new(start_vpn, end_vpn),
             data_frames: BTreeMap::new(),
+            swap_copies: BTreeMap::new(),
+            file: None,
             map_type,
             map_perm,
         }
@@ -341,37 +542,243 @@ impl MapArea {
         Self {
             vpn_range: VPNRange::new(another.vpn_range.get_start(), another.vpn_range.get_end()),
             data_frames: BTreeMap::new(),
+            swap_copies: BTreeMap::new(),
+            file: another.file.clone(),
             map_type: another.map_type,
             map_perm: another.map_perm,
         }
     }
     pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        assert!(self.try_map_one(page_table, vpn), "No frame left!");
+    }
+    /// Map the page at `vpn`, and return false if there is no frame for it
+    pub fn try_map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
         let ppn: PhysPageNum;
         match self.map_type {
             MapType::Identical => {
                 ppn = PhysPageNum(vpn.0);
             }
             MapType::Framed => {
-                let frame = frame_alloc().unwrap();
+                let frame = match frame_alloc() {
+                    Some(frame) => frame,
+                    None => return false,
+                };
+                if let Some(file) = self.file.as_ref() {
+                    // past the end of the file the page stays zeroed
+                    file.inode
+                        .read_at(self.file_offset(vpn), frame.ppn.get_bytes_array());
+                }
                 ppn = frame.ppn;
                 self.data_frames.insert(vpn, frame);
             }
         }
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
         page_table.map(vpn, ppn, pte_flags);
+        true
     }
 
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        #[allow(clippy::single_match)]
         match self.map_type {
             MapType::Framed => {
-                self.data_frames.remove(&vpn);
+                self.write_back(page_table, vpn);
+                if let Some(slot) = self.swap_copies.remove(&vpn) {
+                    swap_free(slot);
+                }
+                // untouched pages of a lazy area have nothing mapped
+                if self.data_frames.remove(&vpn).is_some() {
+                    page_table.unmap(vpn);
+                } else if let Some(slot) = page_table.translate(vpn).and_then(|pte| pte.swap_slot())
+                {
+                    swap_free(slot);
+                    page_table.clear_swapped(vpn);
+                }
+            }
+            MapType::Identical => page_table.unmap(vpn),
+        }
+    }
+    /// A copy of this shared file mapping, mapping the same frames in
+    /// `new_page_table`
+    pub fn share(&self, new_page_table: &mut PageTable) -> Self {
+        let mut new_area = Self::from_another(self);
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        for (vpn, frame) in self.data_frames.iter() {
+            new_page_table.map(*vpn, frame.ppn, pte_flags);
+            new_area.data_frames.insert(*vpn, frame.share());
+        }
+        new_area
+    }
+    /// A copy of this area sharing its frames, which are mapped read-only in
+    /// both `page_table` and `new_page_table` until written to
+    ///
+    /// Pages swapped out share their slots instead. Copies kept in swap are
+    /// dropped, since remapping the frames forgets whether they are dirty.
+    pub fn share_cow(&mut self, page_table: &mut PageTable, new_page_table: &mut PageTable) -> Self {
+        assert_eq!(self.map_type, MapType::Framed);
+        let mut new_area = Self::from_another(self);
+        let pte_flags = PTEFlags::from_bits((self.map_perm - MapPermission::W).bits).unwrap();
+        for (vpn, frame) in self.data_frames.iter() {
+            page_table.remap(*vpn, frame.ppn, pte_flags);
+            new_page_table.map(*vpn, frame.ppn, pte_flags);
+            new_area.data_frames.insert(*vpn, frame.share());
+        }
+        for (_, slot) in core::mem::take(&mut self.swap_copies) {
+            swap_free(slot);
+        }
+        for vpn in self.vpn_range {
+            if let Some(slot) = page_table.translate(vpn).and_then(|pte| pte.swap_slot()) {
+                swap_dup(slot);
+                new_page_table.set_swapped(vpn, slot);
             }
-            _ => {}
+        }
+        new_area
+    }
+    /// Swap out the page at `vpn` if the clock hand finds it unreferenced,
+    /// and return whether it did
+    ///
+    /// A referenced page has its A bit cleared and is given another chance.
+    /// A clean page, whose copy in swap is up to date, is simply dropped;
+    /// other pages have to be written out, which is left for later rounds
+    /// unless `take_dirty`. Pages shared with other processes, or with a
+    /// syscall blocking on a user buffer, stay. Pages of shared file mappings
+    /// go back to their files instead of swap.
+    pub fn swap_out_one(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+        take_dirty: bool,
+    ) -> bool {
+        let frame = self.data_frames.get(&vpn).unwrap();
+        if frame.ref_count() > 1 {
+            return false;
+        }
+        let pte = page_table.translate(vpn).unwrap();
+        if pte.accessed() {
+            page_table.set_flags(vpn, pte.flags() - PTEFlags::A);
+            return false;
+        }
+        if self.is_shared_file() {
+            if pte.dirty() && !take_dirty {
+                return false;
+            }
+            // read from the file again on the next access
+            self.write_back(page_table, vpn);
+            page_table.unmap(vpn);
+            self.data_frames.remove(&vpn);
+            return true;
+        }
+        let clean = !pte.dirty() && self.swap_copies.contains_key(&vpn);
+        if !clean && !take_dirty {
+            return false;
+        }
+        let slot = match self.swap_copies.remove(&vpn).or_else(swap_alloc) {
+            Some(slot) => slot,
+            // swap is full
+            None => return false,
+        };
+        if !clean {
+            swap_write(slot, frame.ppn);
         }
         page_table.unmap(vpn);
+        page_table.set_swapped(vpn, slot);
+        self.data_frames.remove(&vpn);
+        true
+    }
+    /// Read the page at `vpn` back in from `slot`, which is kept as a copy
+    /// of it unless another process still refers to it. Return false if
+    /// there is no frame for the page.
+    pub fn swap_in(&mut self, page_table: &mut PageTable, vpn: VirtPageNum, slot: usize) -> bool {
+        let frame = match frame_alloc() {
+            Some(frame) => frame,
+            None => return false,
+        };
+        swap_read(slot, frame.ppn);
+        if swap_ref_count(slot) == 1 {
+            self.swap_copies.insert(vpn, slot);
+        } else {
+            swap_free(slot);
+        }
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        page_table.clear_swapped(vpn);
+        page_table.map(vpn, frame.ppn, pte_flags);
+        self.data_frames.insert(vpn, frame);
+        true
+    }
+    /// Make the page at `vpn`, shared copy-on-write, writable: it is copied
+    /// to a new frame unless nobody else refers to it any more. Return false
+    /// if there is no frame for the copy.
+    pub fn copy_on_write(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        let frame = self.data_frames.get(&vpn).unwrap();
+        if frame.ref_count() > 1 {
+            let new_frame = match frame_alloc() {
+                Some(frame) => frame,
+                None => return false,
+            };
+            new_frame
+                .ppn
+                .get_bytes_array()
+                .copy_from_slice(frame.ppn.get_bytes_array());
+            // drops our reference to the shared frame
+            self.data_frames.insert(vpn, new_frame);
+        }
+        let ppn = self.data_frames.get(&vpn).unwrap().ppn;
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+        page_table.remap(vpn, ppn, pte_flags);
+        true
+    }
+    /// Whether the area maps a file with MAP_SHARED
+    fn is_shared_file(&self) -> bool {
+        self.file.as_ref().map_or(false, |file| file.shared)
+    }
+    /// Where the page at `vpn` is in the file mapped
+    fn file_offset(&self, vpn: VirtPageNum) -> usize {
+        let file = self.file.as_ref().unwrap();
+        file.offset + (vpn.0 - self.vpn_range.get_start().0) * PAGE_SIZE
+    }
+    /// Write the page at `vpn` back to the file if the area is a shared
+    /// file mapping and the page is dirty
+    ///
+    /// D is left set, since clearing it would need every TLB caching it to
+    /// be flushed, so the page is written again the next time. Only the
+    /// part inside the file is written: mappings do not extend their files.
+    fn write_back(&self, page_table: &PageTable, vpn: VirtPageNum) {
+        if !self.is_shared_file() {
+            return;
+        }
+        let frame = match self.data_frames.get(&vpn) {
+            Some(frame) => frame,
+            None => return,
+        };
+        if !page_table.translate(vpn).unwrap().dirty() {
+            return;
+        }
+        let inode = &self.file.as_ref().unwrap().inode;
+        let offset = self.file_offset(vpn);
+        let size = inode.size();
+        if offset < size {
+            let len = PAGE_SIZE.min(size - offset);
+            inode.write_at(offset, &frame.ppn.get_bytes_array()[..len]);
+        }
+    }
+    /// Map the pages from the end of the area up to `new_end`
+    pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
+        if !self.is_lazy() {
+            for vpn in VPNRange::new(self.vpn_range.get_end(), new_end) {
+                self.map_one(page_table, vpn);
+            }
+        }
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
+    }
+    /// Unmap the pages from `new_end` up to the end of the area
+    pub fn shrink_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
+        for vpn in VPNRange::new(new_end, self.vpn_range.get_end()) {
+            self.unmap_one(page_table, vpn);
+        }
+        self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
     }
     pub fn map(&mut self, page_table: &mut PageTable) {
+        if self.is_lazy() {
+            return;
+        }
         for vpn in self.vpn_range {
             self.map_one(page_table, vpn);
         }
@@ -383,12 +790,21 @@ impl MapArea {
     }
     /// data: start-aligned but maybe with shorter length
     /// assume that all frames were cleared before
+    ///
+    /// The pages of a lazy area are mapped here as far as data goes, the
+    /// rest is left to page faults.
     pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8]) {
         assert_eq!(self.map_type, MapType::Framed);
         let mut start: usize = 0;
         let mut current_vpn = self.vpn_range.get_start();
         let len = data.len();
+        if len == 0 {
+            return;
+        }
         loop {
+            if !self.data_frames.contains_key(&current_vpn) {
+                self.map_one(page_table, current_vpn);
+            }
             let src = &data[start..len.min(start + PAGE_SIZE)];
             let dst = &mut page_table
                 .translate(current_vpn)
@@ -405,6 +821,43 @@ impl MapArea {
     }
 }
 
+/// The part of a file mapped by an area, from `offset` on
+#[derive(Clone)]
+pub struct FileMapping {
+    inode: Arc<Inode>,
+    offset: usize,
+    /// Whether writes go to the file (MAP_SHARED), instead of staying
+    /// private to the process (MAP_PRIVATE)
+    shared: bool,
+}
+
+impl FileMapping {
+    pub fn new(inode: Arc<Inode>, offset: usize, shared: bool) -> Self {
+        Self {
+            inode,
+            offset,
+            shared,
+        }
+    }
+}
+
+/// The kind of access causing a page fault
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PageFaultKind {
+    Load,
+    Store,
+    Fetch,
+}
+
+/// Why a page fault of the user could not be handled
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PageFaultError {
+    /// No area allows the access
+    Denied,
+    /// The access is allowed, but no frame is left for the page
+    NoFrame,
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 /// map type for memory set: identical or framed
 pub enum MapType {
@@ -424,7 +877,7 @@ bitflags! {
 
 #[allow(unused)]
 pub fn remap_test() {
-    let mut kernel_space = KERNEL_SPACE.exclusive_access();
+    let mut kernel_space = KERNEL_SPACE.lock();
     let mid_text: VirtAddr = ((stext as usize + etext as usize) / 2).into();
     let mid_rodata: VirtAddr = ((srodata as usize + erodata as usize) / 2).into();
     let mid_data: VirtAddr = ((sdata as usize + edata as usize) / 2).into();
@@ -5,6 +5,7 @@
 //! map area and memory set, is implemented here.
 //!
 //! Every task or process has a memory_set to control its virtual memory.
+//! User pages may be swapped out to the block device when frames run low.
 
 
 mod address;
@@ -12,18 +13,26 @@ mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
 pub mod page_table;
+mod swap;
 
 pub use address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 pub use address::{StepByOne, VPNRange};
-pub use frame_allocator::{frame_alloc, frame_dealloc, FrameTracker};
+pub use frame_allocator::{frame_alloc, frame_dealloc, free_frame_count, FrameTracker};
 pub use memory_set::{remap_test, kernel_token};
-pub use memory_set::{MapPermission, MemorySet, KERNEL_SPACE};
-pub use page_table::{translated_byte_buffer, translated_refmut, translated_ref, translated_str, PageTableEntry};
+pub use memory_set::{
+    FileMapping, MapPermission, MemorySet, PageFaultError, PageFaultKind, KERNEL_SPACE,
+};
+pub use page_table::{copy_to_user, translated_byte_buffer, translated_refmut, translated_ref, translated_str, PageTableEntry};
 pub use page_table::{PTEFlags, PageTable, UserBuffer};
 
 /// initiate heap allocator, frame allocator and kernel space
 pub fn init() {
     heap_allocator::init_heap();
     frame_allocator::init_frame_allocator();
-    KERNEL_SPACE.exclusive_access().activate();
+    KERNEL_SPACE.lock().activate();
+}
+
+/// turn on paging with kernel space on a hart other than the boot one
+pub fn init_other_hart() {
+    KERNEL_SPACE.lock().activate();
 }
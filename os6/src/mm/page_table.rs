@@ -1,6 +1,8 @@
 //! Implementation of [`PageTableEntry`] and [`PageTable`].
 
-use super::{frame_alloc, FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
+use super::{frame_alloc, FrameTracker, PageFaultKind, PhysAddr, PhysPageNum};
+use super::{StepByOne, VirtAddr, VirtPageNum};
+use crate::task::handle_page_fault;
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
@@ -20,6 +22,10 @@ bitflags! {
     }
 }
 
+/// RSW bit of an invalid PTE marking a page swapped out, in which case the
+/// ppn field holds its swap slot instead
+const PTE_SWAPPED: usize = 1 << 8;
+
 #[derive(Copy, Clone)]
 #[repr(C)]
 /// page table entry structure
@@ -36,6 +42,16 @@ impl PageTableEntry {
     pub fn empty() -> Self {
         PageTableEntry { bits: 0 }
     }
+    /// An invalid PTE for a page swapped out to `slot`
+    pub fn swapped(slot: usize) -> Self {
+        PageTableEntry {
+            bits: slot << 10 | PTE_SWAPPED,
+        }
+    }
+    /// The swap slot of a page swapped out, None for any other PTE
+    pub fn swap_slot(&self) -> Option<usize> {
+        (!self.is_valid() && self.bits & PTE_SWAPPED != 0).then(|| self.bits >> 10)
+    }
     pub fn ppn(&self) -> PhysPageNum {
         (self.bits >> 10 & ((1usize << 44) - 1)).into()
     }
@@ -54,6 +70,12 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    pub fn accessed(&self) -> bool {
+        (self.flags() & PTEFlags::A) != PTEFlags::empty()
+    }
+    pub fn dirty(&self) -> bool {
+        (self.flags() & PTEFlags::D) != PTEFlags::empty()
+    }
 }
 
 /// page table structure
@@ -120,12 +142,37 @@ impl PageTable {
         assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
     }
+    /// Point the mapping of `vpn` to `ppn` with `flags` instead
+    pub fn remap(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
+        let pte = self.find_pte_create(vpn).unwrap();
+        assert!(pte.is_valid(), "vpn {:?} is invalid before remapping", vpn);
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+    }
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPageNum) {
         let pte = self.find_pte_create(vpn).unwrap();
         assert!(pte.is_valid(), "vpn {:?} is invalid before unmapping", vpn);
         *pte = PageTableEntry::empty();
     }
+    /// Replace the flags of the mapping of `vpn`, e.g. to clear A
+    pub fn set_flags(&mut self, vpn: VirtPageNum, flags: PTEFlags) {
+        let pte = self.find_pte_create(vpn).unwrap();
+        assert!(pte.is_valid(), "vpn {:?} is not mapped", vpn);
+        *pte = PageTableEntry::new(pte.ppn(), flags | PTEFlags::V);
+    }
+    /// Mark `vpn` as swapped out to `slot`, instead of mapped
+    pub fn set_swapped(&mut self, vpn: VirtPageNum, slot: usize) {
+        let pte = self.find_pte_create(vpn).unwrap();
+        assert!(!pte.is_valid(), "vpn {:?} is mapped", vpn);
+        *pte = PageTableEntry::swapped(slot);
+    }
+    /// Forget the swap slot of `vpn`, which is then neither mapped nor
+    /// swapped out
+    pub fn clear_swapped(&mut self, vpn: VirtPageNum) {
+        let pte = self.find_pte_create(vpn).unwrap();
+        assert!(pte.swap_slot().is_some(), "vpn {:?} is not swapped", vpn);
+        *pte = PageTableEntry::empty();
+    }
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.find_pte(vpn).copied()
     }
@@ -144,8 +191,48 @@ impl PageTable {
     }
 }
 
-/// translate a pointer to a mutable u8 Vec through page table
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
+/// The frame of `vpn` in user space, which is about to be written to if
+/// `write`, or None if the user may not access the page that way
+///
+/// The kernel accesses user memory through frames, behind the back of the
+/// MMU, so it first handles the page fault the user would have taken, e.g.
+/// copying a page shared copy-on-write. A page about to be written to is
+/// also marked dirty, as the MMU does not see the kernel writing to it. The
+/// page table then has to be the one of the current process, whose PCB must
+/// not be locked by the caller.
+fn translated_ppn(page_table: &PageTable, vpn: VirtPageNum, write: bool) -> Option<PhysPageNum> {
+    match page_table.translate(vpn) {
+        Some(pte)
+            if pte.is_valid()
+                && pte.flags().contains(PTEFlags::U)
+                && (!write || pte.writable() && pte.dirty()) =>
+        {
+            return Some(pte.ppn())
+        }
+        _ => {}
+    }
+    let kind = if write {
+        PageFaultKind::Store
+    } else {
+        PageFaultKind::Load
+    };
+    if !handle_page_fault(VirtAddr::from(vpn).into(), kind) {
+        return None;
+    }
+    page_table
+        .translate(vpn)
+        .filter(|pte| pte.is_valid())
+        .map(|pte| pte.ppn())
+}
+
+/// translate a pointer to a u8 Vec through page table, which is about to be
+/// written to if `write`, or None if some page of it may not be accessed
+pub fn translated_byte_buffer(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+    write: bool,
+) -> Option<Vec<&'static mut [u8]>> {
     let page_table = PageTable::from_token(token);
     let mut start = ptr as usize;
     let end = start + len;
@@ -153,7 +240,7 @@ pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&
     while start < end {
         let start_va = VirtAddr::from(start);
         let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
+        let ppn = translated_ppn(&page_table, vpn, write)?;
         vpn.step();
         let mut end_va: VirtAddr = vpn.into();
         end_va = end_va.min(VirtAddr::from(end));
@@ -164,18 +251,40 @@ pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&
         }
         start = end_va.into();
     }
-    v
+    Some(v)
 }
 
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
+/// Copy `value` to `ptr` in user space, even if it straddles a page boundary,
+/// and return whether the user may write there
+pub fn copy_to_user<T>(token: usize, ptr: *mut T, value: &T) -> bool {
+    let len = core::mem::size_of::<T>();
+    let src = unsafe { core::slice::from_raw_parts(value as *const T as *const u8, len) };
+    let buffers = match translated_byte_buffer(token, ptr as *const u8, len, true) {
+        Some(buffers) => buffers,
+        None => return false,
+    };
+    let mut copied = 0;
+    for buffer in buffers {
+        buffer.copy_from_slice(&src[copied..copied + buffer.len()]);
+        copied += buffer.len();
+    }
+    true
+}
+
+/// The physical address of `va` in user space, see [`translated_ppn`]
+fn translated_pa(page_table: &PageTable, va: usize, write: bool) -> Option<PhysAddr> {
+    let va = VirtAddr::from(va);
+    let aligned_pa: PhysAddr = translated_ppn(page_table, va.floor(), write)?.into();
+    let aligned_pa_usize: usize = aligned_pa.into();
+    Some((aligned_pa_usize + va.page_offset()).into())
+}
+
+pub fn translated_str(token: usize, ptr: *const u8) -> Option<String> {
     let page_table = PageTable::from_token(token);
     let mut string = String::new();
     let mut va = ptr as usize;
     loop {
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
+        let ch: u8 = *(translated_pa(&page_table, va, false)?.get_mut());
         if ch == 0 {
             break;
         } else {
@@ -183,34 +292,41 @@ pub fn translated_str(token: usize, ptr: *const u8) -> String {
             va += 1;
         }
     }
-    string
+    Some(string)
 }
 
-pub fn translated_ref<T>(token: usize, ptr: *const T) -> &'static T {
+pub fn translated_ref<T>(token: usize, ptr: *const T) -> Option<&'static T> {
     let page_table = PageTable::from_token(token);
-    page_table.translate_va(VirtAddr::from(ptr as usize)).unwrap().get_mut()
+    translated_pa(&page_table, ptr as usize, false).map(|pa| &*pa.get_mut())
 }
 
-pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
-    //println!("into translated_refmut!");
+pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> Option<&'static mut T> {
     let page_table = PageTable::from_token(token);
-    let va = ptr as usize;
-    //println!("translated_refmut: before translate_va");
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_mut()
+    translated_pa(&page_table, ptr as usize, true).map(|pa| pa.get_mut())
 }
 
 /// An abstraction over a buffer passed from user space to kernel space
+///
+/// The frames of the buffer are referenced as long as it lives, so that they
+/// are not swapped out while a syscall blocks with it, e.g. reading a pipe:
+/// the swapper leaves frames with other references alone.
 pub struct UserBuffer {
     pub buffers: Vec<&'static mut [u8]>,
+    frames: Vec<FrameTracker>,
 }
 
 impl UserBuffer {
     /// Constuct a UserBuffer
     pub fn new(buffers: Vec<&'static mut [u8]>) -> Self {
-        Self { buffers }
+        // the kernel maps physical memory identically, and only frames handed
+        // out by the allocator can be swapped out, hence need to be pinned
+        let frames = buffers
+            .iter()
+            .filter_map(|buffer| {
+                FrameTracker::try_from_ppn(PhysAddr::from(buffer.as_ptr() as usize).floor())
+            })
+            .collect();
+        Self { buffers, frames }
     }
     /// Get the length of a UserBuffer
     pub fn len(&self) -> usize {
@@ -228,6 +344,7 @@ impl IntoIterator for UserBuffer {
     fn into_iter(self) -> Self::IntoIter {
         UserBufferIterator {
             buffers: self.buffers,
+            _frames: self.frames,
             current_buffer: 0,
             current_idx: 0,
         }
@@ -237,6 +354,8 @@ impl IntoIterator for UserBuffer {
 // An iterator over a UserBuffer
 pub struct UserBufferIterator {
     buffers: Vec<&'static mut [u8]>,
+    /// Only held, see [`UserBuffer`]
+    _frames: Vec<FrameTracker>,
     current_buffer: usize,
     current_idx: usize,
 }
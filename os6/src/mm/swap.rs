@@ -0,0 +1,103 @@
+//! Swap area on the block device, which user pages are swapped out to
+//!
+//! The area is cut into slots of a page each, which sit right after the
+//! blocks of easy-fs and are read and written bypassing its block cache.
+//! Like frames, slots are reference counted: a page swapped out before a
+//! fork is shared by both processes until either swaps it back in.
+
+use super::PhysPageNum;
+use crate::config::{PAGE_SIZE, SWAP_SLOT_NUM, SWAP_START_BLOCK};
+use crate::drivers::BLOCK_DEVICE;
+use crate::sync::SpinNoIrqLock;
+use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// Size of a block of the block device
+const BLOCK_SZ: usize = 512;
+/// Blocks of a slot
+const BLOCKS_PER_SLOT: usize = PAGE_SIZE / BLOCK_SZ;
+
+/// Allocator of swap slots, handing out the recycled ones first
+struct SwapSlotAllocator {
+    current: usize,
+    recycled: Vec<usize>,
+    /// Reference counts indexed by slot, 0 for free slots
+    ref_counts: Vec<u16>,
+}
+
+impl SwapSlotAllocator {
+    fn new() -> Self {
+        Self {
+            current: 0,
+            recycled: Vec::new(),
+            ref_counts: vec![0; SWAP_SLOT_NUM],
+        }
+    }
+    fn alloc(&mut self) -> Option<usize> {
+        let slot = if let Some(slot) = self.recycled.pop() {
+            slot
+        } else if self.current == SWAP_SLOT_NUM {
+            return None;
+        } else {
+            self.current += 1;
+            self.current - 1
+        };
+        self.ref_counts[slot] = 1;
+        Some(slot)
+    }
+    fn ref_count_mut(&mut self, slot: usize) -> &mut u16 {
+        let ref_count = &mut self.ref_counts[slot];
+        // validity check
+        if *ref_count == 0 {
+            panic!("Swap slot {} has not been allocated!", slot);
+        }
+        ref_count
+    }
+}
+
+lazy_static! {
+    static ref SWAP_ALLOCATOR: SpinNoIrqLock<SwapSlotAllocator> =
+        SpinNoIrqLock::new(SwapSlotAllocator::new());
+}
+
+/// Allocate a slot with a single reference, None if swap is full
+pub fn swap_alloc() -> Option<usize> {
+    SWAP_ALLOCATOR.lock().alloc()
+}
+
+/// Add a reference to `slot`
+pub fn swap_dup(slot: usize) {
+    *SWAP_ALLOCATOR.lock().ref_count_mut(slot) += 1;
+}
+
+/// Drop a reference to `slot`, recycling it if it was the last one
+pub fn swap_free(slot: usize) {
+    let mut allocator = SWAP_ALLOCATOR.lock();
+    let ref_count = allocator.ref_count_mut(slot);
+    *ref_count -= 1;
+    if *ref_count == 0 {
+        allocator.recycled.push(slot);
+    }
+}
+
+/// Number of references to `slot`
+pub fn swap_ref_count(slot: usize) -> usize {
+    SWAP_ALLOCATOR.lock().ref_counts[slot] as usize
+}
+
+/// Write the frame `ppn` out to `slot`
+pub fn swap_write(slot: usize, ppn: PhysPageNum) {
+    let bytes = ppn.get_bytes_array();
+    for (i, block) in bytes.chunks(BLOCK_SZ).enumerate() {
+        BLOCK_DEVICE.write_block(SWAP_START_BLOCK + slot * BLOCKS_PER_SLOT + i, block);
+    }
+}
+
+/// Read `slot` into the frame `ppn`
+pub fn swap_read(slot: usize, ppn: PhysPageNum) {
+    let bytes = ppn.get_bytes_array();
+    for (i, block) in bytes.chunks_mut(BLOCK_SZ).enumerate() {
+        BLOCK_DEVICE.read_block(SWAP_START_BLOCK + slot * BLOCKS_PER_SLOT + i, block);
+    }
+}
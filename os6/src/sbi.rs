@@ -6,6 +6,10 @@ const SBI_SET_TIMER: usize = 0;
 const SBI_CONSOLE_PUTCHAR: usize = 1;
 const SBI_CONSOLE_GETCHAR: usize = 2;
 const SBI_SHUTDOWN: usize = 8;
+/// Hart State Management extension, whose function 0 is hart_start
+const SBI_EXT_HSM: usize = 0x48534D;
+/// IPI extension, whose function 0 is send_ipi
+const SBI_EXT_IPI: usize = 0x735049;
 
 #[inline(always)]
 /// general sbi call
@@ -39,6 +43,19 @@ pub fn console_getchar() -> usize {
     sbi_call(SBI_CONSOLE_GETCHAR, 0, 0, 0)
 }
 
+/// use sbi call to start hart `hart_id` at physical address `start_addr`,
+/// with its hart id in a0 and `opaque` in a1; return the SBI error code
+pub fn hart_start(hart_id: usize, start_addr: usize, opaque: usize) -> usize {
+    // function id 0 is passed in a6 by sbi_call
+    sbi_call(SBI_EXT_HSM, hart_id, start_addr, opaque)
+}
+
+/// use sbi call to raise a supervisor software interrupt on the harts in
+/// `hart_mask`, where bit i stands for hart i
+pub fn send_ipi(hart_mask: usize) {
+    sbi_call(SBI_EXT_IPI, hart_mask, 0, 0);
+}
+
 /// use sbi call to shutdown the kernel
 pub fn shutdown() -> ! {
     sbi_call(SBI_SHUTDOWN, 0, 0, 0);
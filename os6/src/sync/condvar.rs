@@ -0,0 +1,52 @@
+//! Condition variables that can be handed out to user threads
+
+use super::{Mutex, SpinNoIrqLock, WaitQueue};
+use crate::task::block_current_and_run_next;
+use alloc::sync::Arc;
+
+/// Condition variable, always used together with a [`Mutex`]
+pub struct Condvar {
+    pub inner: SpinNoIrqLock<CondvarInner>,
+}
+
+pub struct CondvarInner {
+    pub wait_queue: WaitQueue,
+}
+
+impl Condvar {
+    pub fn new() -> Self {
+        Self {
+            inner: SpinNoIrqLock::new(CondvarInner {
+                wait_queue: WaitQueue::new(),
+            }),
+        }
+    }
+
+    /// Wake up one waiter, if any
+    pub fn signal(&self) {
+        let mut inner = self.inner.lock();
+        inner.wait_queue.wake_one();
+    }
+
+    /// Release `mutex` and block until signaled, then reacquire `mutex`
+    ///
+    /// A [`Condvar::signal`] on another hart may slip in between enqueueing
+    /// and blocking; it is not lost, as [`block_current_and_run_next`] only
+    /// yields if we have been woken up before blocking.
+    /// Return false if `mutex` was not locked, in which case nothing is
+    /// waited for, or if a signal interrupted the wait, in which case `mutex`
+    /// is still reacquired, unless the signal interrupts that as well.
+    pub fn wait(&self, mutex: Arc<dyn Mutex>) -> bool {
+        let mut inner = self.inner.lock();
+        if !mutex.unlock() {
+            return false;
+        }
+        inner.wait_queue.add_current_interruptible();
+        drop(inner);
+        let signaled = block_current_and_run_next();
+        if !signaled {
+            self.inner.lock().wait_queue.remove_current();
+        }
+        mutex.lock() && signaled
+    }
+}
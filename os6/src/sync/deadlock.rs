@@ -0,0 +1,131 @@
+//! Deadlock avoidance with the banker's algorithm
+//!
+//! Each process keeps one [`DeadlockDetector`] per kind of resource
+//! (mutexes, semaphores). Rows are indexed by TID, columns by the id of
+//! the resource in the table of the process.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Available, Allocation and Need of the banker's algorithm
+pub struct DeadlockDetector {
+    /// Free instances of each resource
+    available: Vec<usize>,
+    /// Instances of each resource held by each thread
+    allocation: Vec<Vec<usize>>,
+    /// Instances of each resource each thread is waiting for
+    need: Vec<Vec<usize>>,
+}
+
+impl DeadlockDetector {
+    pub fn new() -> Self {
+        Self {
+            available: Vec::new(),
+            allocation: Vec::new(),
+            need: Vec::new(),
+        }
+    }
+
+    /// Grow the matrices so that thread `tid` has a row
+    fn ensure_thread(&mut self, tid: usize) {
+        while self.allocation.len() <= tid {
+            self.allocation.push(vec![0; self.available.len()]);
+            self.need.push(vec![0; self.available.len()]);
+        }
+    }
+
+    /// A resource with `count` instances has been created at `res_id`,
+    /// which is either a new id or one recycled from a dropped resource
+    pub fn add_resource(&mut self, res_id: usize, count: usize) {
+        while self.available.len() <= res_id {
+            self.available.push(0);
+            for row in self.allocation.iter_mut().chain(self.need.iter_mut()) {
+                row.push(0);
+            }
+        }
+        self.available[res_id] = count;
+        for row in self.allocation.iter_mut().chain(self.need.iter_mut()) {
+            row[res_id] = 0;
+        }
+    }
+
+    /// Thread `tid` has just been created, forget about any former
+    /// thread with the same TID
+    pub fn reset_thread(&mut self, tid: usize) {
+        self.ensure_thread(tid);
+        self.allocation[tid].fill(0);
+        self.need[tid].fill(0);
+    }
+
+    /// Thread `tid` asks for one instance of `res_id`.
+    ///
+    /// If `check` is set and granting the request could end up in a
+    /// deadlock, the request is withdrawn and false is returned.
+    pub fn request(&mut self, tid: usize, res_id: usize, check: bool) -> bool {
+        self.ensure_thread(tid);
+        self.need[tid][res_id] += 1;
+        if check && !self.is_safe() {
+            self.need[tid][res_id] -= 1;
+            return false;
+        }
+        true
+    }
+
+    /// Thread `tid` gives up the instance of `res_id` it requested
+    pub fn withdraw(&mut self, tid: usize, res_id: usize) {
+        self.ensure_thread(tid);
+        self.need[tid][res_id] -= 1;
+    }
+
+    /// Thread `tid` has been granted the instance of `res_id` it requested
+    pub fn acquire(&mut self, tid: usize, res_id: usize) {
+        self.ensure_thread(tid);
+        self.need[tid][res_id] -= 1;
+        self.allocation[tid][res_id] += 1;
+        self.available[res_id] -= 1;
+    }
+
+    /// Whether thread `tid` holds an instance of `res_id`
+    pub fn holds(&self, tid: usize, res_id: usize) -> bool {
+        self.allocation
+            .get(tid)
+            .map_or(false, |row| row[res_id] > 0)
+    }
+
+    /// Thread `tid` gives back one instance of `res_id`
+    ///
+    /// Semaphores may be released by a thread which never acquired them,
+    /// so the allocation only goes down when there is something to release.
+    pub fn release(&mut self, tid: usize, res_id: usize) {
+        self.ensure_thread(tid);
+        if self.allocation[tid][res_id] > 0 {
+            self.allocation[tid][res_id] -= 1;
+        }
+        self.available[res_id] += 1;
+    }
+
+    /// Safety check of the banker's algorithm: whether all threads can
+    /// finish in some order with what is available now
+    fn is_safe(&self) -> bool {
+        let mut work = self.available.clone();
+        let mut finish = vec![false; self.need.len()];
+        loop {
+            let next = (0..self.need.len()).find(|&tid| {
+                !finish[tid]
+                    && self.need[tid]
+                        .iter()
+                        .zip(work.iter())
+                        .all(|(need, work)| need <= work)
+            });
+            match next {
+                Some(tid) => {
+                    for (work, allocation) in work.iter_mut().zip(self.allocation[tid].iter()) {
+                        *work += allocation;
+                    }
+                    finish[tid] = true;
+                }
+                None => return finish.iter().all(|f| *f),
+            }
+        }
+    }
+}
@@ -1,5 +1,15 @@
 //! Synchronization and interior mutability primitives
 
-mod up;
+mod condvar;
+mod deadlock;
+mod mutex;
+mod semaphore;
+mod spin;
+mod wait_queue;
 
-pub use up::UPSafeCell;
+pub use condvar::Condvar;
+pub use deadlock::DeadlockDetector;
+pub use mutex::{Mutex, MutexBlocking, MutexSpin};
+pub use semaphore::Semaphore;
+pub use spin::{SpinNoIrqLock, SpinNoIrqLockGuard};
+pub use wait_queue::WaitQueue;
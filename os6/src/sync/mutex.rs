@@ -0,0 +1,104 @@
+//! Mutexes that can be handed out to user threads
+
+use super::{SpinNoIrqLock, WaitQueue};
+use crate::task::{
+    block_current_and_run_next, current_signal_pending, suspend_current_and_run_next,
+};
+
+/// Common interface of the mutexes kept in the mutex table of a process
+pub trait Mutex: Sync + Send {
+    /// Lock the mutex, and return false if a signal interrupted the wait
+    /// for it, which leaves it unlocked
+    fn lock(&self) -> bool;
+    /// Unlock the mutex, and return false if it was not locked
+    fn unlock(&self) -> bool;
+}
+
+/// Mutex that yields the CPU while it is contended
+pub struct MutexSpin {
+    locked: SpinNoIrqLock<bool>,
+}
+
+impl MutexSpin {
+    pub fn new() -> Self {
+        Self {
+            locked: SpinNoIrqLock::new(false),
+        }
+    }
+}
+
+impl Mutex for MutexSpin {
+    fn lock(&self) -> bool {
+        loop {
+            let mut locked = self.locked.lock();
+            if *locked {
+                drop(locked);
+                if current_signal_pending() {
+                    return false;
+                }
+                suspend_current_and_run_next();
+                continue;
+            } else {
+                *locked = true;
+                return true;
+            }
+        }
+    }
+
+    fn unlock(&self) -> bool {
+        let mut locked = self.locked.lock();
+        core::mem::replace(&mut *locked, false)
+    }
+}
+
+/// Mutex that parks contending threads in a wait queue
+pub struct MutexBlocking {
+    inner: SpinNoIrqLock<MutexBlockingInner>,
+}
+
+pub struct MutexBlockingInner {
+    locked: bool,
+    wait_queue: WaitQueue,
+}
+
+impl MutexBlocking {
+    pub fn new() -> Self {
+        Self {
+            inner: SpinNoIrqLock::new(MutexBlockingInner {
+                locked: false,
+                wait_queue: WaitQueue::new(),
+            }),
+        }
+    }
+}
+
+impl Mutex for MutexBlocking {
+    fn lock(&self) -> bool {
+        let mut mutex_inner = self.inner.lock();
+        if mutex_inner.locked {
+            mutex_inner.wait_queue.add_current_interruptible();
+            drop(mutex_inner);
+            if !block_current_and_run_next() {
+                // unlock skips us from now on
+                self.inner.lock().wait_queue.remove_current();
+                return false;
+            }
+            // the lock has been handed over to us by unlock
+        } else {
+            mutex_inner.locked = true;
+        }
+        true
+    }
+
+    /// Hand the lock over to the first waiter, if any, without releasing it
+    fn unlock(&self) -> bool {
+        let mut mutex_inner = self.inner.lock();
+        if !mutex_inner.locked {
+            return false;
+        }
+        if !mutex_inner.wait_queue.wake_one() {
+            mutex_inner.locked = false;
+        }
+        true
+    }
+}
@@ -0,0 +1,55 @@
+//! Counting semaphores that can be handed out to user threads
+
+use super::{SpinNoIrqLock, WaitQueue};
+use crate::task::block_current_and_run_next;
+
+/// Semaphore with a wait queue
+pub struct Semaphore {
+    pub inner: SpinNoIrqLock<SemaphoreInner>,
+}
+
+pub struct SemaphoreInner {
+    /// Number of available resources, or minus the number of waiters
+    pub count: isize,
+    pub wait_queue: WaitQueue,
+}
+
+impl Semaphore {
+    pub fn new(res_count: usize) -> Self {
+        Self {
+            inner: SpinNoIrqLock::new(SemaphoreInner {
+                count: res_count as isize,
+                wait_queue: WaitQueue::new(),
+            }),
+        }
+    }
+
+    /// Release a resource and wake up one waiter, if any
+    pub fn up(&self) {
+        let mut inner = self.inner.lock();
+        inner.count += 1;
+        if inner.count <= 0 {
+            inner.wait_queue.wake_one();
+        }
+    }
+
+    /// Acquire a resource, blocking until one is available, and return
+    /// false if a signal interrupted the wait
+    pub fn down(&self) -> bool {
+        let mut inner = self.inner.lock();
+        inner.count -= 1;
+        if inner.count < 0 {
+            inner.wait_queue.add_current_interruptible();
+            drop(inner);
+            if !block_current_and_run_next() {
+                let mut inner = self.inner.lock();
+                inner.wait_queue.remove_current();
+                // no longer a waiter; if up has skipped us in the meantime,
+                // the resource it released is available again
+                inner.count += 1;
+                return false;
+            }
+        }
+        true
+    }
+}
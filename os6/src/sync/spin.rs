@@ -0,0 +1,77 @@
+//! Spinlock for multiprocessor
+
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+use riscv::register::sstatus;
+
+/// Spinlock which masks supervisor interrupts on the local hart while it is
+/// held, so that an interrupt handler can never spin on a lock owned by the
+/// code it interrupted.
+///
+/// Locking it twice on the same hart deadlocks.
+pub struct SpinNoIrqLock<T> {
+    locked: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for SpinNoIrqLock<T> {}
+unsafe impl<T: Send> Send for SpinNoIrqLock<T> {}
+
+/// Gives access to the data until it is dropped
+pub struct SpinNoIrqLockGuard<'a, T> {
+    lock: &'a SpinNoIrqLock<T>,
+    /// Whether interrupts were enabled before locking
+    sie: bool,
+}
+
+impl<T> SpinNoIrqLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            data: UnsafeCell::new(value),
+        }
+    }
+    /// Spin until the lock is acquired
+    pub fn lock(&self) -> SpinNoIrqLockGuard<'_, T> {
+        let sie = sstatus::read().sie();
+        unsafe {
+            sstatus::clear_sie();
+        }
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            while self.locked.load(Ordering::Relaxed) {
+                spin_loop();
+            }
+        }
+        SpinNoIrqLockGuard { lock: self, sie }
+    }
+}
+
+impl<T> Deref for SpinNoIrqLockGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for SpinNoIrqLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for SpinNoIrqLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+        if self.sie {
+            unsafe {
+                sstatus::set_sie();
+            }
+        }
+    }
+}
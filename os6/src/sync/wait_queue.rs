@@ -0,0 +1,57 @@
+//! Queue of threads waiting for some event
+
+use crate::task::{current_task, set_current_waiting, wakeup_task, TaskControlBlock};
+use alloc::{collections::VecDeque, sync::Arc};
+
+/// FIFO queue of blocked threads
+///
+/// It does not lock itself: it is meant to live next to the state it guards,
+/// inside the same [`super::SpinNoIrqLock`]. A thread enqueues itself with
+/// [`WaitQueue::add_current`], then releases every lock it holds and calls
+/// [`crate::task::block_current_and_run_next`]. A thread interrupted by a
+/// signal is skipped by the wakeups, and takes itself out of the queue with
+/// [`WaitQueue::remove_current`].
+pub struct WaitQueue {
+    queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl WaitQueue {
+    pub fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+    /// Put the current thread at the end of the queue
+    pub fn add_current(&mut self) {
+        set_current_waiting(false);
+        self.queue.push_back(current_task().unwrap());
+    }
+    /// Put the current thread at the end of the queue, to be woken up by a
+    /// signal as well
+    pub fn add_current_interruptible(&mut self) {
+        set_current_waiting(true);
+        self.queue.push_back(current_task().unwrap());
+    }
+    /// Take the current thread out of the queue after a signal interrupted
+    /// its wait. A wakeup may have skipped it already.
+    pub fn remove_current(&mut self) {
+        let task = current_task().unwrap();
+        self.queue.retain(|waiter| !Arc::ptr_eq(waiter, &task));
+    }
+    /// Wake up the first waiter, return false if there is none
+    pub fn wake_one(&mut self) -> bool {
+        while let Some(task) = self.queue.pop_front() {
+            if wakeup_task(task) {
+                return true;
+            }
+        }
+        false
+    }
+    /// Wake up every waiter
+    pub fn wake_all(&mut self) {
+        while self.wake_one() {}
+    }
+}
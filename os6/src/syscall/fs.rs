@@ -1,34 +1,35 @@
 //! File and filesystem-related syscalls
 
-use crate::mm::PageTable;
 use crate::mm::PhysAddr;
-use crate::mm::VirtAddr;
 use crate::mm::translated_byte_buffer;
 use crate::mm::translated_str;
 use crate::mm::translated_refmut;
 use crate::task::current_user_token;
-use crate::task::current_task;
-use crate::fs::{open_file, linkat, unlinkat};
+use crate::task::current_process;
+use crate::fs::{open_file, linkat, unlinkat, make_pipe};
 use crate::fs::OpenFlags;
 use crate::fs::Stat;
 use crate::mm::UserBuffer;
+use crate::config::MAX_FD_NUM;
 use alloc::sync::Arc;
 use riscv::addr::Page;
 
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     let token = current_user_token();
-    let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
     if fd >= inner.fd_table.len() {
         return -1;
     }
     if let Some(file) = &inner.fd_table[fd] {
         let file = file.clone();
-        // release current task TCB manually to avoid multi-borrow
+        // release current PCB manually to avoid multi-borrow
         drop(inner);
-        file.write(
-            UserBuffer::new(translated_byte_buffer(token, buf, len))
-        ) as isize
+        // the buffer is only read from
+        match translated_byte_buffer(token, buf, len, false) {
+            Some(buffers) => file.write(UserBuffer::new(buffers)) as isize,
+            None => -1,
+        }
     } else {
         -1
     }
@@ -36,32 +37,36 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
 
 pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
     let token = current_user_token();
-    let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
     if fd >= inner.fd_table.len() {
         return -1;
     }
     if let Some(file) = &inner.fd_table[fd] {
         let file = file.clone();
-        // release current task TCB manually to avoid multi-borrow
+        // release current PCB manually to avoid multi-borrow
         drop(inner);
-        file.read(
-            UserBuffer::new(translated_byte_buffer(token, buf, len))
-        ) as isize
+        match translated_byte_buffer(token, buf, len, true) {
+            Some(buffers) => file.read(UserBuffer::new(buffers)) as isize,
+            None => -1,
+        }
     } else {
         -1
     }
 }
 
 pub fn sys_open(path: *const u8, flags: u32) -> isize {
-    let task = current_task().unwrap();
+    let process = current_process();
     let token = current_user_token();
-    let path = translated_str(token, path);
+    let path = match translated_str(token, path) {
+        Some(path) => path,
+        None => return -1,
+    };
     if let Some(inode) = open_file(
         path.as_str(),
         OpenFlags::from_bits(flags).unwrap()
     ) {
-        let mut inner = task.inner_exclusive_access();
+        let mut inner = process.inner_exclusive_access();
         let fd = inner.alloc_fd();
         inner.fd_table[fd] = Some(inode);
         fd as isize
@@ -71,8 +76,8 @@ pub fn sys_open(path: *const u8, flags: u32) -> isize {
 }
 
 pub fn sys_close(fd: usize) -> isize {
-    let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
     if fd >= inner.fd_table.len() {
         return -1;
     }
@@ -83,6 +88,69 @@ pub fn sys_close(fd: usize) -> isize {
     0
 }
 
+/// Duplicate `fd` into the lowest free slot of the fd table
+pub fn sys_dup(fd: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if fd >= inner.fd_table.len() {
+        return -1;
+    }
+    if inner.fd_table[fd].is_none() {
+        return -1;
+    }
+    let new_fd = inner.alloc_fd();
+    inner.fd_table[new_fd] = Some(Arc::clone(inner.fd_table[fd].as_ref().unwrap()));
+    new_fd as isize
+}
+
+/// Make `new_fd` refer to the same file as `old_fd`, closing whatever
+/// `new_fd` referred to before. Both happen under one borrow of the PCB,
+/// so no other fd allocation can sneak in between.
+///
+/// No flags are supported yet, so `flags` must be 0.
+pub fn sys_dup3(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    if flags != 0 || old_fd == new_fd || new_fd >= MAX_FD_NUM {
+        return -1;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if old_fd >= inner.fd_table.len() {
+        return -1;
+    }
+    if inner.fd_table[old_fd].is_none() {
+        return -1;
+    }
+    while inner.fd_table.len() <= new_fd {
+        inner.fd_table.push(None);
+    }
+    inner.fd_table[new_fd] = inner.fd_table[old_fd].clone();
+    new_fd as isize
+}
+
+/// Create a pipe and write its read end and write end fds into `pipe[0]` and `pipe[1]`
+pub fn sys_pipe(pipe: *mut usize) -> isize {
+    let process = current_process();
+    let token = current_user_token();
+    // writing to user space may fault, so check it before locking the PCB
+    let (read_fd_ref, write_fd_ref) = match (
+        translated_refmut(token, pipe),
+        translated_refmut(token, unsafe { pipe.add(1) }),
+    ) {
+        (Some(read_fd_ref), Some(write_fd_ref)) => (read_fd_ref, write_fd_ref),
+        _ => return -1,
+    };
+    let mut inner = process.inner_exclusive_access();
+    let (pipe_read, pipe_write) = make_pipe();
+    let read_fd = inner.alloc_fd();
+    inner.fd_table[read_fd] = Some(pipe_read);
+    let write_fd = inner.alloc_fd();
+    inner.fd_table[write_fd] = Some(pipe_write);
+    drop(inner);
+    *read_fd_ref = read_fd;
+    *write_fd_ref = write_fd;
+    0
+}
+
 // YOUR JOB: 扩展 easy-fs 和内核以实现以下三个 syscall
 // NOTE: 这个应该就是一个类似于时间读取的部分，按照我们获取时间的方案重新获取一次就可以了？
 // 通过指针获取对应内存位置，然后通过unsafe包裹向raw pointer(还是啥别的)里面写入对应的数据内容
@@ -95,21 +163,9 @@ pub fn sys_close(fd: usize) -> isize {
 #[allow(dead_code, unused_variables)]
 pub fn sys_fstat(_fd: usize, _st: *mut Stat) -> isize {
     
-    /* +====================+ GET ADDR +====================+ */
-    // BUG REPORT: BC we have using page_table in PageTable::from_token this function will using current_task()
-    // when we putting it inside under the create of inner, then will return error because of we borrow it in two position
-    // and BC it's extral posistion of this two things, it will case a DEADLOCK which will only case timeout to return
-    let virtaddr = VirtAddr::from(_st as usize);
-    debug!("VA:{}", usize::from(virtaddr));
-    let physaddr = PageTable::from_token(current_user_token())
-    .translate_va(virtaddr)
-                                                    .unwrap();
-    debug!("PA: {}", usize::from(physaddr));
-    let pointer = usize::from(physaddr) as *mut Stat;
-    
     // FIX 需要判断是否可能出现写不完的情况？
-    let task = current_task().unwrap();
-    let inner = task.inner_exclusive_access();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
 
     // bounds checking
     if _fd >= inner.fd_table.len() {
@@ -128,16 +184,16 @@ pub fn sys_fstat(_fd: usize, _st: *mut Stat) -> isize {
         // println!("REPORT:{}", inode.fstat());
         println!("finish get fstat");
 
-        // debug!("get PA:{:?} successed {}", physaddr, usize::from(physaddr));
-        let pointer = usize::from(physaddr) as *mut Stat;
-        debug!("get pointer successed");
-        // println!("finish translation");
-        unsafe {
-            (*pointer).dev = 0;
-            (*pointer).ino = ino;
-            (*pointer).mode = mode;
-            (*pointer).nlink = nlink;
-        }
+        // written to after releasing current PCB, as it may fault, e.g. on
+        // a page shared copy-on-write with the parent
+        let stat = match translated_refmut(current_user_token(), _st) {
+            Some(stat) => stat,
+            None => return -1,
+        };
+        stat.dev = 0;
+        stat.ino = ino;
+        stat.mode = mode;
+        stat.nlink = nlink;
     } else {
         println!("[WARN]: couldn't get fd_table");
         return -1
@@ -150,8 +206,13 @@ pub fn sys_fstat(_fd: usize, _st: *mut Stat) -> isize {
 
 pub fn sys_linkat(_old_name: *const u8, _new_name: *const u8) -> isize {
     let token = current_user_token();
-    let old_path = translated_str(token, _old_name);
-    let new_path = translated_str(token, _new_name);
+    let (old_path, new_path) = match (
+        translated_str(token, _old_name),
+        translated_str(token, _new_name),
+    ) {
+        (Some(old_path), Some(new_path)) => (old_path, new_path),
+        _ => return -1,
+    };
 
     // base on the given possibliy result
     if old_path == new_path {
@@ -164,7 +225,10 @@ pub fn sys_linkat(_old_name: *const u8, _new_name: *const u8) -> isize {
 
 pub fn sys_unlinkat(_name: *const u8) -> isize {
     let token = current_user_token();
-    let file_name = translated_str(token, _name);
+    let file_name = match translated_str(token, _name) {
+        Some(file_name) => file_name,
+        None => return -1,
+    };
 
     unlinkat(file_name.as_str())
 }
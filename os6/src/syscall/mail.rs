@@ -0,0 +1,74 @@
+//! Mailbox syscalls, a lightweight IPC between processes
+
+use crate::config::MAX_MAIL_LEN;
+use crate::mm::{translated_byte_buffer, UserBuffer};
+use crate::task::{current_process, current_user_token, pid2process};
+use alloc::vec::Vec;
+
+/// Move the oldest message of the current process into `buf`, truncated to
+/// `len` bytes, and return the number of bytes read.
+/// Return -1 if there is no message, or `buf` is invalid, in which case
+/// the message stays in the mailbox. With `len` == 0, only probe whether
+/// there is a message to read.
+pub fn sys_mail_read(buf: *mut u8, len: usize) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    if process.inner_exclusive_access().mailbox.is_empty() {
+        return -1;
+    }
+    if len == 0 {
+        return 0;
+    }
+    // longer messages cannot be there; the buffer is checked before taking
+    // the message, without the PCB locked as pages may be faulted in
+    let len = len.min(MAX_MAIL_LEN);
+    let user_buf = match translated_byte_buffer(token, buf, len, true) {
+        Some(buffers) => UserBuffer::new(buffers),
+        None => return -1,
+    };
+    // another thread may have taken the message in the meantime
+    let mail = match process.inner_exclusive_access().mailbox.pop() {
+        Some(mail) => mail,
+        None => return -1,
+    };
+    let len = len.min(mail.len());
+    for (byte_ref, byte) in user_buf.into_iter().zip(mail.iter()) {
+        unsafe {
+            *byte_ref = *byte;
+        }
+    }
+    len as isize
+}
+
+/// Send `len` bytes from `buf` to the mailbox of process `pid`, and return
+/// the number of bytes sent, which is at most [`MAX_MAIL_LEN`].
+/// Return -1 if there is no such process or its mailbox is full. With
+/// `len` == 0, only probe whether a message can be sent.
+pub fn sys_mail_write(pid: usize, buf: *const u8, len: usize) -> isize {
+    let target = match pid2process(pid) {
+        Some(process) => process,
+        None => return -1,
+    };
+    if target.inner_exclusive_access().mailbox.is_full() {
+        return -1;
+    }
+    if len == 0 {
+        return 0;
+    }
+    // longer messages are truncated anyway
+    let len = len.min(MAX_MAIL_LEN);
+    let token = current_user_token();
+    let user_buf = match translated_byte_buffer(token, buf, len, false) {
+        Some(buffers) => UserBuffer::new(buffers),
+        None => return -1,
+    };
+    let mail: Vec<u8> = user_buf
+        .into_iter()
+        .map(|byte_ref| unsafe { *byte_ref })
+        .collect();
+    let mut target_inner = target.inner_exclusive_access();
+    match target_inner.mailbox.push(mail) {
+        Some(len) => len as isize,
+        None => -1,
+    }
+}
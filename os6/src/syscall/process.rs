@@ -1,13 +1,15 @@
 //! Process management syscalls
 
 #[allow(unused_imports)]
-use crate::mm::{translated_refmut, translated_ref, translated_str};
+use crate::mm::{copy_to_user, translated_refmut, translated_ref, translated_str, FileMapping};
 use crate::task::{
-    add_task, current_task, current_user_token, exit_current_and_run_next,
-    suspend_current_and_run_next, TaskStatus,
+    block_current_and_run_next, current_process, current_task, current_trap_cx,
+    current_user_token, exit_current_and_run_next, pgid2processes, pid2process, send_group_signal,
+    send_signal, set_current_waiting, set_realtime, suspend_current_and_run_next, SignalAction,
+    SignalFlags, TaskStatus,
 };
-use crate::fs::{open_file, OpenFlags, File};
-use crate::timer::get_time_us;
+use crate::fs::{open_file, set_tty_foreground_pgid, tty_foreground_pgid, OpenFlags, File};
+use crate::timer::{add_timer, get_time_ms, get_time_us, remove_timer};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use crate::config::{MAX_SYSCALL_NUM, PAGE_SIZE};
@@ -39,82 +41,183 @@ pub fn sys_yield() -> isize {
     0
 }
 
+/// current thread sleeps for `ms` milliseconds without being scheduled,
+/// return -1 if a signal wakes it up before
+pub fn sys_sleep(ms: usize) -> isize {
+    let expire_ms = get_time_ms() + ms;
+    let task = current_task().unwrap();
+    set_current_waiting(true);
+    add_timer(expire_ms, Arc::clone(&task));
+    if !block_current_and_run_next() {
+        remove_timer(task);
+        return -1;
+    }
+    0
+}
+
 pub fn sys_getpid() -> isize {
-    current_task().unwrap().pid.0 as isize
+    current_process().getpid() as isize
 }
 
 /// Syscall Fork which returns 0 for child process and child_pid for parent process
+///
+/// The main thread of the child is already in the ready queue, with a0 in its
+/// trap context set to 0. Return -1 if the process has more than one thread.
 pub fn sys_fork() -> isize {
-    let current_task = current_task().unwrap();
-    let new_task = current_task.fork();
-    let new_pid = new_task.pid.0;
-    // modify trap context of new_task, because it returns immediately after switching
-    let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
-    // we do not have to move to next instruction since we have done it before
-    // for child process, fork returns 0
-    trap_cx.x[10] = 0;
-    // add new task to scheduler
-    add_task(new_task);
-    new_pid as isize
-}
-
-/// Syscall Exec which accepts the elf path
-pub fn sys_exec(path: *const u8) -> isize {
+    let current_process = current_process();
+    match current_process.fork() {
+        Some(new_process) => new_process.getpid() as isize,
+        None => -1,
+    }
+}
+
+/// Syscall Exec which accepts the elf path and a null-terminated argument vector
+///
+/// Return -1 if the process has more than one thread.
+pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
     let token = current_user_token();
-    let path = translated_str(token, path);
+    let path = match translated_str(token, path) {
+        Some(path) => path,
+        None => return -1,
+    };
+    let mut args_vec: Vec<String> = Vec::new();
+    loop {
+        let arg_str_ptr = match translated_ref(token, args) {
+            Some(arg_str_ptr) => *arg_str_ptr,
+            None => return -1,
+        };
+        if arg_str_ptr == 0 {
+            break;
+        }
+        match translated_str(token, arg_str_ptr as *const u8) {
+            Some(arg) => args_vec.push(arg),
+            None => return -1,
+        }
+        unsafe {
+            args = args.add(1);
+        }
+    }
     if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
         let all_data = app_inode.read_all();
-        let task = current_task().unwrap();
-        task.exec(all_data.as_slice());
-        0
+        let process = current_process();
+        let argc = args_vec.len();
+        if !process.exec(all_data.as_slice(), args_vec) {
+            return -1;
+        }
+        // return argc because cx.x[10] will be covered with it later
+        argc as isize
     } else {
         -1
     }
 }
 
 
+bitflags! {
+    /// Options of sys_waitpid
+    pub struct WaitFlags: usize {
+        /// Return -2 at once instead of blocking if no child has exited
+        const WNOHANG = 1 << 0;
+        /// Also return for a child stopped by a signal
+        const WUNTRACED = 1 << 1;
+    }
+}
+
+/// Reap a child process whose pid is `pid`, or any child if `pid` is -1,
+/// and return its pid, blocking until it exits.
 /// If there is not a child process whose pid is same as given, return -1.
-/// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
-    let task = current_task().unwrap();
-    // find a child process
-
-    // ---- access current TCB exclusively
-    let mut inner = task.inner_exclusive_access();
-    if !inner
-        .children
-        .iter()
-        .any(|p| pid == -1 || pid as usize == p.getpid())
-    {
+/// Else if there is a child process but it is still running and WNOHANG is
+/// set, return -2.
+/// With WUNTRACED, a child which has stopped since last reported is returned
+/// as well, without being reaped: `(signum << 8) | 0x7f` is written as its
+/// exit code then.
+/// Return -1 as well if a signal interrupts the wait.
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32, options: usize) -> isize {
+    let options = match WaitFlags::from_bits(options) {
+        Some(options) => options,
+        None => return -1,
+    };
+    // fail before reaping a child rather than losing its exit code
+    if translated_refmut(current_user_token(), exit_code_ptr).is_none() {
         return -1;
-        // ---- release current PCB
     }
-    let pair = inner.children.iter().enumerate().find(|(_, p)| {
-        // ++++ temporarily access child PCB lock exclusively
-        p.inner_exclusive_access().is_zombie() && (pid == -1 || pid as usize == p.getpid())
-        // ++++ release child PCB
-    });
-    if let Some((idx, _)) = pair {
-        let child = inner.children.remove(idx);
-        // confirm that child will be deallocated after removing from children list
-        assert_eq!(Arc::strong_count(&child), 1);
-        let found_pid = child.getpid();
-        // ++++ temporarily access child TCB exclusively
-        let exit_code = child.inner_exclusive_access().exit_code;
-        // ++++ release child PCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
-        found_pid as isize
-    } else {
-        -2
+    let process = current_process();
+    loop {
+        // ---- access current PCB exclusively
+        let mut inner = process.inner_exclusive_access();
+        // find a child process
+        if !inner
+            .children
+            .iter()
+            .any(|p| pid == -1 || pid as usize == p.getpid())
+        {
+            return -1;
+            // ---- release current PCB
+        }
+        let pair = inner.children.iter().enumerate().find(|(_, p)| {
+            // ++++ temporarily access child PCB lock exclusively
+            p.inner_exclusive_access().is_zombie() && (pid == -1 || pid as usize == p.getpid())
+            // ++++ release child PCB
+        });
+        if let Some((idx, _)) = pair {
+            // the main thread of the child may still be exiting on another
+            // hart, so the PCB is not necessarily deallocated right here
+            let child = inner.children.remove(idx);
+            let found_pid = child.getpid();
+            // ++++ temporarily access child PCB exclusively
+            let exit_code = child.inner_exclusive_access().exit_code;
+            // ++++ release child PCB
+            let token = inner.memory_set.token();
+            drop(inner);
+            // ---- release current PCB, as writing to user space may fault
+            match translated_refmut(token, exit_code_ptr) {
+                Some(exit_code_ref) => *exit_code_ref = exit_code,
+                None => return -1,
+            }
+            return found_pid as isize;
+        }
+        if options.contains(WaitFlags::WUNTRACED) {
+            let stopped = inner.children.iter().find_map(|p| {
+                if pid != -1 && pid as usize != p.getpid() {
+                    return None;
+                }
+                // ++++ temporarily access child PCB exclusively
+                let signum = p.inner_exclusive_access().stop_report.take()?;
+                Some((p.getpid(), signum))
+            });
+            if let Some((found_pid, signum)) = stopped {
+                let exit_code = ((signum << 8) | 0x7f) as i32;
+                let token = inner.memory_set.token();
+                drop(inner);
+                // ---- release current PCB
+                match translated_refmut(token, exit_code_ptr) {
+                    Some(exit_code_ref) => *exit_code_ref = exit_code,
+                    None => return -1,
+                }
+                return found_pid as isize;
+            }
+        }
+        if options.contains(WaitFlags::WNOHANG) {
+            return -2;
+        }
+        // woken up by a child exiting, or stopping in handle_signals
+        inner.child_exit.add_current_interruptible();
+        drop(inner);
+        // ---- release current PCB
+        if !block_current_and_run_next() {
+            process.inner_exclusive_access().child_exit.remove_current();
+            return -1;
+        }
     }
-    // ---- release current PCB lock automatically
 }
 
 // YOUR JOB: 引入虚地址后重写 sys_get_time
 pub fn sys_get_time(ts: *mut TimeVal, _tz: usize) -> isize {
     let us = get_time_us();
     let token = current_user_token();
-    let ptr = translated_refmut(token,ts);
+    let ptr = match translated_refmut(token, ts) {
+        Some(ptr) => ptr,
+        None => return -1,
+    };
 
     *ptr = TimeVal {
         sec: us / 1_000_000,
@@ -123,18 +226,240 @@ pub fn sys_get_time(ts: *mut TimeVal, _tz: usize) -> isize {
     0
 }
 
-// YOUR JOB: 引入虚地址后重写 sys_task_info
+/// Report the status, syscall counters and run time of the current thread
 pub fn sys_task_info(ti: *mut TaskInfo) -> isize {
-    -1
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let info = TaskInfo {
+        status: inner.task_status,
+        syscall_times: inner.syscall_times,
+        time: get_time_ms() - inner.first_run_time.unwrap(),
+    };
+    drop(inner);
+    if !copy_to_user(current_user_token(), ti, &info) {
+        return -1;
+    }
+    0
+}
+
+/// Set the stride scheduling priority of the current thread, which must be
+/// at least 2, and return it
+pub fn sys_set_priority(prio: isize) -> isize {
+    if prio < 2 {
+        return -1;
+    }
+    current_task().unwrap().inner_exclusive_access().sched.priority = prio as usize;
+    prio
+}
+
+/// Make the current thread a real-time one, scheduled by EDF: in each
+/// `period_ms` it may run for `runtime_ms` within `deadline_ms` of the start
+/// of the period. A `deadline_ms` of 0 means the whole period, and a
+/// `period_ms` of 0 makes the thread a normal one again.
+/// Return -1 if the parameters are invalid or the CPU cannot take them.
+pub fn sys_set_realtime(period_ms: usize, runtime_ms: usize, deadline_ms: usize) -> isize {
+    let deadline_ms = if deadline_ms == 0 { period_ms } else { deadline_ms };
+    let task = current_task().unwrap();
+    if set_realtime(&task, period_ms * 1000, runtime_ms * 1000, deadline_ms * 1000) {
+        0
+    } else {
+        -1
+    }
 }
 
-// YOUR JOB: 实现sys_set_priority，为任务添加优先级
-pub fn sys_set_priority(_prio: isize) -> isize {
-    -1
+/// Send signal `signum` to process `pid`, or to every process in group
+/// `-pid` if `pid` is negative. A `signum` of 0 only checks whether the
+/// target exists. Return -1 if either is invalid.
+pub fn sys_kill(pid: isize, signum: usize) -> isize {
+    let signal = match SignalFlags::from_signum(signum) {
+        Some(signal) => Some(signal),
+        None if signum == 0 => None,
+        None => return -1,
+    };
+    if pid < 0 {
+        let pgid = -pid as usize;
+        let found = match signal {
+            Some(signal) => send_group_signal(pgid, signal),
+            None => !pgid2processes(pgid).is_empty(),
+        };
+        return if found { 0 } else { -1 };
+    }
+    let process = match pid2process(pid as usize) {
+        Some(process) => process,
+        None => return -1,
+    };
+    if let Some(signal) = signal {
+        send_signal(&process, signal);
+    }
+    0
+}
+
+/// Move process `pid`, which is the current process if `pid` is 0, into
+/// process group `pgid`, which is a new group led by it if `pgid` is 0.
+/// Return -1 unless it is the current process or one of its children, and
+/// the group is either new or already exists.
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    let current = current_process();
+    let process = if pid == 0 || pid == current.getpid() {
+        current
+    } else {
+        // ---- temporarily access current PCB exclusively
+        let child = current
+            .inner_exclusive_access()
+            .children
+            .iter()
+            .find(|child| child.getpid() == pid)
+            .cloned();
+        match child {
+            Some(child) => child,
+            None => return -1,
+        }
+    };
+    let pgid = if pgid == 0 { process.getpid() } else { pgid };
+    if pgid != process.getpid() && pgid2processes(pgid).is_empty() {
+        return -1;
+    }
+    process.inner_exclusive_access().pgid = pgid;
+    0
+}
+
+/// Return the process group of process `pid`, or of the current process if
+/// `pid` is 0, or -1 if there is no such process
+pub fn sys_getpgid(pid: usize) -> isize {
+    let process = if pid == 0 {
+        current_process()
+    } else {
+        match pid2process(pid) {
+            Some(process) => process,
+            None => return -1,
+        }
+    };
+    let pgid = process.inner_exclusive_access().pgid;
+    pgid as isize
+}
+
+/// Make `pgid` the foreground process group of the console, which gets
+/// ^C and ^Z and may read from it. Return -1 if there is no such group.
+pub fn sys_tcsetpgrp(pgid: usize) -> isize {
+    if pgid2processes(pgid).is_empty() {
+        return -1;
+    }
+    set_tty_foreground_pgid(pgid);
+    0
+}
+
+/// Return the foreground process group of the console
+pub fn sys_tcgetpgrp() -> isize {
+    tty_foreground_pgid() as isize
+}
+
+/// Set the handler of signal `signum` to `*action` unless it is null, and
+/// save the previous one in `*old_action` unless it is null.
+/// Return -1 if the signal is invalid or its handler cannot be changed.
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    match SignalFlags::from_signum(signum) {
+        Some(signal) if !SignalFlags::unmaskable().contains(signal) => {}
+        _ => return -1,
+    }
+    let token = current_user_token();
+    // user space is accessed without holding the PCB, as it may fault
+    let new_action = if action.is_null() {
+        None
+    } else {
+        let new_action = match translated_ref(token, action) {
+            Some(new_action) => new_action,
+            None => return -1,
+        };
+        Some(SignalAction {
+            handler: new_action.handler,
+            mask: SignalFlags::from_bits_truncate(new_action.mask.bits()),
+        })
+    };
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let prev_action = inner.signal_actions.table[signum];
+    if let Some(new_action) = new_action {
+        inner.signal_actions.table[signum] = new_action;
+    }
+    drop(inner);
+    if !old_action.is_null() && !copy_to_user(token, old_action, &prev_action) {
+        return -1;
+    }
+    0
+}
+
+/// Replace the set of blocked signals of the current process with `mask`,
+/// and return the previous one. SIGKILL and SIGSTOP cannot be blocked.
+pub fn sys_sigprocmask(mask: u32) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let old_mask = inner.signal_mask;
+    inner.signal_mask = SignalFlags::from_bits_truncate(mask) - SignalFlags::unmaskable();
+    old_mask.bits() as isize
+}
+
+/// Return from a signal handler to where the thread was interrupted.
+/// Return -1 if the thread is not running a handler.
+pub fn sys_sigreturn() -> isize {
+    let task = current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let trap_cx_backup = match task_inner.trap_cx_backup.take() {
+        Some(trap_cx_backup) => trap_cx_backup,
+        None => return -1,
+    };
+    task_inner.handling_sig = None;
+    drop(task_inner);
+    let trap_cx = current_trap_cx();
+    *trap_cx = trap_cx_backup;
+    // a0 is overwritten with the return value of the syscall
+    trap_cx.x[10] as isize
+}
+
+/// Move the program break of the current process to `addr`, and return the
+/// new break, or the current one if `addr` is 0. Return -1 if the heap
+/// cannot grow or shrink that far.
+pub fn sys_brk(addr: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if addr != 0 && !inner.memory_set.set_brk(addr) {
+        return -1;
+    }
+    inner.memory_set.brk() as isize
+}
+
+bitflags! {
+    /// Flags of sys_mmap, at most one of MAP_SHARED and MAP_PRIVATE
+    pub struct MmapFlags: usize {
+        /// Writes go to the file mapped, and are seen by children
+        const MAP_SHARED = 1 << 0;
+        /// Writes stay private to the process, copied on write after fork
+        const MAP_PRIVATE = 1 << 1;
+        /// Map zeroed memory instead of a file, ignoring fd and offset
+        const MAP_ANONYMOUS = 1 << 5;
+    }
 }
 
 // YOUR JOB: 扩展内核以实现 sys_mmap 和 sys_munmap
-pub fn sys_mmap(_start: usize, mut _len: usize, _port: usize) -> isize {
+/// Map `_len` bytes at `_start`, either anonymous memory or the file `fd`
+/// from `offset` on, which has to be page aligned
+///
+/// Pages of a file are read from it on the first access. Those of a shared
+/// mapping are written back on munmap, msync and exit, which is why the
+/// file must be writable for a writable one. Shared anonymous memory is not
+/// supported. Without MAP_SHARED or MAP_PRIVATE, private anonymous memory is
+/// mapped, as the three argument mmap of the earlier chapters did.
+pub fn sys_mmap(
+    _start: usize,
+    mut _len: usize,
+    _port: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> isize {
 
     if (_start % PAGE_SIZE) != 0 { return -1; }
     if _port & !0x7 != 0 || _port & 0x7 == 0 { return -1; }
@@ -142,34 +467,78 @@ pub fn sys_mmap(_start: usize, mut _len: usize, _port: usize) -> isize {
     if _len % PAGE_SIZE != 0 {
         _len = ( _len / PAGE_SIZE + 1 ) * PAGE_SIZE;
     } 
-    
-    crate::task::mmap(_start, _len, _port)
+
+    let mut flags = match MmapFlags::from_bits(flags) {
+        Some(flags) => flags,
+        None => return -1,
+    };
+    if !flags.intersects(MmapFlags::MAP_SHARED | MmapFlags::MAP_PRIVATE) {
+        flags = MmapFlags::MAP_PRIVATE | MmapFlags::MAP_ANONYMOUS;
+    }
+    let shared = flags.contains(MmapFlags::MAP_SHARED);
+    if shared && flags.contains(MmapFlags::MAP_PRIVATE) {
+        return -1;
+    }
+    let file = if flags.contains(MmapFlags::MAP_ANONYMOUS) {
+        if shared {
+            return -1;
+        }
+        None
+    } else {
+        if offset % PAGE_SIZE != 0 {
+            return -1;
+        }
+        let process = current_process();
+        let inner = process.inner_exclusive_access();
+        let file = match inner.fd_table.get(fd) {
+            Some(Some(file)) => file.clone(),
+            _ => return -1,
+        };
+        drop(inner);
+        if !file.readable() || (shared && _port & 0x2 != 0 && !file.writable()) {
+            return -1;
+        }
+        match file.inode() {
+            Some(inode) => Some(FileMapping::new(inode, offset, shared)),
+            // not a regular file
+            None => return -1,
+        }
+    };
+    crate::task::mmap(_start, _len, _port, file)
 }
 
 pub fn sys_munmap(_start: usize, _len: usize) -> isize {
-    println!("Inside sys_munmap!!!");
     if _start % PAGE_SIZE != 0 || _len % PAGE_SIZE != 0 {
-        println!("out because page not match");
         return -1;
     }
 
     crate::task::unmmap(_start, _len) 
 }
 
+/// Write the dirty pages of shared file mappings in `[start, start + len)`
+/// back to their files, return -1 if some page there is not mapped
+pub fn sys_msync(start: usize, len: usize, _flags: usize) -> isize {
+    if start % PAGE_SIZE != 0 {
+        return -1;
+    }
+    crate::task::msync(start, len)
+}
+
 // YOUR JOB: 实现 sys_spawn 系统调用
 // ALERT: 注意在实现 SPAWN 时不需要复制父进程地址空间，SPAWN != FORK + EXEC 
 pub fn sys_spawn(_path: *const u8) -> isize {
    
     let token = current_user_token();
-    let name = translated_str(token, _path);
+    let name = match translated_str(token, _path) {
+        Some(name) => name,
+        None => return -1,
+    };
 
     if let Some(app_inode) = open_file(name.as_str(), OpenFlags::RDONLY) {
         let data = app_inode.read_all();
-        let task = current_task().unwrap();
-        let task_control_block = task.create_new_task_from_elf(data.as_slice());
-        let pid = task_control_block.pid.0;
-        add_task(task_control_block);
-        pid as isize
+        let process = current_process();
+        let new_process = process.spawn(data.as_slice());
+        new_process.getpid() as isize
     } else {
         -1
     }
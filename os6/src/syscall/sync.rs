@@ -0,0 +1,234 @@
+//! Synchronization syscalls
+
+use crate::sync::{Condvar, Mutex, MutexBlocking, MutexSpin, Semaphore};
+use crate::task::{current_process, current_task};
+use alloc::sync::Arc;
+
+/// Returned by lock and down requests refused by deadlock detection
+const DEADLOCK_DETECTED: isize = -0xDEAD;
+
+fn current_tid() -> usize {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .tid
+}
+
+/// Create a mutex in the current process and return its id.
+/// A spin mutex is created unless `blocking` is set.
+pub fn sys_mutex_create(blocking: bool) -> isize {
+    let process = current_process();
+    let mutex: Option<Arc<dyn Mutex>> = if !blocking {
+        Some(Arc::new(MutexSpin::new()))
+    } else {
+        Some(Arc::new(MutexBlocking::new()))
+    };
+    let mut process_inner = process.inner_exclusive_access();
+    let id = if let Some(id) = process_inner
+        .mutex_list
+        .iter()
+        .enumerate()
+        .find(|(_, item)| item.is_none())
+        .map(|(id, _)| id)
+    {
+        process_inner.mutex_list[id] = mutex;
+        id
+    } else {
+        process_inner.mutex_list.push(mutex);
+        process_inner.mutex_list.len() - 1
+    };
+    process_inner.mutex_detector.add_resource(id, 1);
+    id as isize
+}
+
+/// Lock the mutex `mutex_id`, return -1 if it does not exist or a signal
+/// interrupts the wait, or -0xDEAD if deadlock detection is on and locking
+/// may deadlock
+pub fn sys_mutex_lock(mutex_id: usize) -> isize {
+    let tid = current_tid();
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let mutex = match process_inner.mutex_list.get(mutex_id) {
+        Some(Some(mutex)) => Arc::clone(mutex),
+        _ => return -1,
+    };
+    let check = process_inner.deadlock_detect;
+    if !process_inner.mutex_detector.request(tid, mutex_id, check) {
+        return DEADLOCK_DETECTED;
+    }
+    drop(process_inner);
+    drop(process);
+    let locked = mutex.lock();
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    if !locked {
+        process_inner.mutex_detector.withdraw(tid, mutex_id);
+        return -1;
+    }
+    process_inner.mutex_detector.acquire(tid, mutex_id);
+    0
+}
+
+/// Unlock the mutex `mutex_id`, return -1 if it does not exist or is not
+/// locked
+pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
+    let tid = current_tid();
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let mutex = match process_inner.mutex_list.get(mutex_id) {
+        Some(Some(mutex)) => Arc::clone(mutex),
+        _ => return -1,
+    };
+    // a thread may unlock a mutex another one locked, and only the latter
+    // gives it back to the detector
+    if process_inner.mutex_detector.holds(tid, mutex_id) {
+        process_inner.mutex_detector.release(tid, mutex_id);
+    }
+    drop(process_inner);
+    drop(process);
+    if !mutex.unlock() {
+        return -1;
+    }
+    0
+}
+
+/// Create a semaphore with `res_count` resources in the current process
+/// and return its id
+pub fn sys_semaphore_create(res_count: usize) -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let id = if let Some(id) = process_inner
+        .semaphore_list
+        .iter()
+        .enumerate()
+        .find(|(_, item)| item.is_none())
+        .map(|(id, _)| id)
+    {
+        process_inner.semaphore_list[id] = Some(Arc::new(Semaphore::new(res_count)));
+        id
+    } else {
+        process_inner
+            .semaphore_list
+            .push(Some(Arc::new(Semaphore::new(res_count))));
+        process_inner.semaphore_list.len() - 1
+    };
+    process_inner.semaphore_detector.add_resource(id, res_count);
+    id as isize
+}
+
+/// Release a resource of the semaphore `sem_id`, return -1 if it does not exist
+pub fn sys_semaphore_up(sem_id: usize) -> isize {
+    let tid = current_tid();
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let sem = match process_inner.semaphore_list.get(sem_id) {
+        Some(Some(sem)) => Arc::clone(sem),
+        _ => return -1,
+    };
+    process_inner.semaphore_detector.release(tid, sem_id);
+    drop(process_inner);
+    drop(process);
+    sem.up();
+    0
+}
+
+/// Acquire a resource of the semaphore `sem_id`, return -1 if it does not exist
+/// or a signal interrupts the wait, or -0xDEAD if deadlock detection is on and
+/// waiting for it may deadlock
+pub fn sys_semaphore_down(sem_id: usize) -> isize {
+    let tid = current_tid();
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let sem = match process_inner.semaphore_list.get(sem_id) {
+        Some(Some(sem)) => Arc::clone(sem),
+        _ => return -1,
+    };
+    let check = process_inner.deadlock_detect;
+    if !process_inner.semaphore_detector.request(tid, sem_id, check) {
+        return DEADLOCK_DETECTED;
+    }
+    drop(process_inner);
+    drop(process);
+    let acquired = sem.down();
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    if !acquired {
+        process_inner.semaphore_detector.withdraw(tid, sem_id);
+        return -1;
+    }
+    process_inner.semaphore_detector.acquire(tid, sem_id);
+    0
+}
+
+/// Turn deadlock detection of the current process on (1) or off (0),
+/// return -1 for any other value
+pub fn sys_enable_deadlock_detect(enabled: usize) -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    match enabled {
+        0 => process_inner.deadlock_detect = false,
+        1 => process_inner.deadlock_detect = true,
+        _ => return -1,
+    }
+    0
+}
+
+/// Create a condition variable in the current process and return its id
+pub fn sys_condvar_create() -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let id = if let Some(id) = process_inner
+        .condvar_list
+        .iter()
+        .enumerate()
+        .find(|(_, item)| item.is_none())
+        .map(|(id, _)| id)
+    {
+        process_inner.condvar_list[id] = Some(Arc::new(Condvar::new()));
+        id
+    } else {
+        process_inner
+            .condvar_list
+            .push(Some(Arc::new(Condvar::new())));
+        process_inner.condvar_list.len() - 1
+    };
+    id as isize
+}
+
+/// Wake up one thread waiting on the condvar `condvar_id`,
+/// return -1 if it does not exist
+pub fn sys_condvar_signal(condvar_id: usize) -> isize {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let condvar = match process_inner.condvar_list.get(condvar_id) {
+        Some(Some(condvar)) => Arc::clone(condvar),
+        _ => return -1,
+    };
+    drop(process_inner);
+    drop(process);
+    condvar.signal();
+    0
+}
+
+/// Wait on the condvar `condvar_id` with the mutex `mutex_id` held,
+/// return -1 if either of them does not exist or a signal interrupts the wait
+pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let (condvar, mutex) = match (
+        process_inner.condvar_list.get(condvar_id),
+        process_inner.mutex_list.get(mutex_id),
+    ) {
+        (Some(Some(condvar)), Some(Some(mutex))) => (Arc::clone(condvar), Arc::clone(mutex)),
+        _ => return -1,
+    };
+    drop(process_inner);
+    drop(process);
+    if !condvar.wait(mutex) {
+        return -1;
+    }
+    0
+}
@@ -0,0 +1,91 @@
+//! Thread management syscalls
+
+use crate::mm::kernel_token;
+use crate::task::{add_task, current_task, TaskControlBlock};
+use crate::trap::{trap_handler, TrapContext};
+use alloc::sync::Arc;
+
+/// Create a thread in the current process which starts at `entry` with `arg` in a0,
+/// and return its TID
+pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    // the TCB must be released before creating the thread locks the PCB
+    let ustack_base = task.inner_exclusive_access().res.as_ref().unwrap().ustack_base;
+    // create a new thread
+    let new_task = Arc::new(TaskControlBlock::new(
+        Arc::clone(&process),
+        ustack_base,
+        true,
+    ));
+    let new_task_inner = new_task.inner_exclusive_access();
+    let new_task_res = new_task_inner.res.as_ref().unwrap();
+    let new_task_tid = new_task_res.tid;
+    let new_task_trap_cx = new_task_inner.get_trap_cx();
+    let new_task_ustack_top = new_task_res.ustack_top();
+    drop(new_task_inner);
+    // add new thread to current process
+    let mut process_inner = process.inner_exclusive_access();
+    let tasks = &mut process_inner.tasks;
+    while tasks.len() < new_task_tid + 1 {
+        tasks.push(None);
+    }
+    tasks[new_task_tid] = Some(Arc::clone(&new_task));
+    // the TID may be a recycled one
+    process_inner.mutex_detector.reset_thread(new_task_tid);
+    process_inner.semaphore_detector.reset_thread(new_task_tid);
+    drop(process_inner);
+    // prepare TrapContext of the new thread
+    *new_task_trap_cx = TrapContext::app_init_context(
+        entry,
+        new_task_ustack_top,
+        kernel_token(),
+        new_task.kstack.get_top(),
+        trap_handler as usize,
+    );
+    new_task_trap_cx.x[10] = arg;
+    // add new thread to scheduler
+    add_task(Arc::clone(&new_task));
+    new_task_tid as isize
+}
+
+pub fn sys_gettid() -> isize {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .tid as isize
+}
+
+/// If the thread does not exist or is the caller itself, return -1.
+/// Else if the thread is still running, return -2.
+/// Otherwise reap the thread and return its exit code.
+pub fn sys_waittid(tid: usize) -> i32 {
+    let task = current_task().unwrap();
+    let process = task.process.upgrade().unwrap();
+    let own_tid = task.inner_exclusive_access().res.as_ref().unwrap().tid;
+    // a thread cannot wait for itself
+    if own_tid == tid {
+        return -1;
+    }
+    let mut process_inner = process.inner_exclusive_access();
+    if tid >= process_inner.tasks.len() {
+        return -1;
+    }
+    let exit_code = if let Some(waited_task) = process_inner.tasks[tid].as_ref() {
+        waited_task.inner_exclusive_access().exit_code
+    } else {
+        // waited thread does not exist
+        return -1;
+    };
+    if let Some(exit_code) = exit_code {
+        // dealloc the exited thread
+        process_inner.tasks[tid] = None;
+        exit_code
+    } else {
+        // waited thread has not exited
+        -2
+    }
+}
@@ -0,0 +1,40 @@
+//! Bounded mailbox of a process
+
+use crate::config::{MAX_MAIL_LEN, MAX_MAIL_NUM};
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// Queue of at most [`MAX_MAIL_NUM`] messages, none of them longer than
+/// [`MAX_MAIL_LEN`] bytes
+pub struct MailBox {
+    mails: VecDeque<Vec<u8>>,
+}
+
+impl MailBox {
+    pub fn new() -> Self {
+        Self {
+            mails: VecDeque::new(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.mails.is_empty()
+    }
+    pub fn is_full(&self) -> bool {
+        self.mails.len() >= MAX_MAIL_NUM
+    }
+    /// Append a message, truncated to [`MAX_MAIL_LEN`] bytes.
+    /// Return the length actually stored, or None if the mailbox is full.
+    pub fn push(&mut self, mut mail: Vec<u8>) -> Option<usize> {
+        if self.is_full() {
+            return None;
+        }
+        mail.truncate(MAX_MAIL_LEN);
+        let len = mail.len();
+        self.mails.push_back(mail);
+        Some(len)
+    }
+    /// Take the oldest message out
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        self.mails.pop_front()
+    }
+}
@@ -1,47 +1,179 @@
 //! Implementation of [`TaskManager`]
 //!
-//! It is only used to manage processes and schedule process based on ready queue.
+//! It is only used to manage threads and schedule threads based on ready queue.
 //! Other CPU process monitoring functions are in Processor.
+//! Live processes can also be looked up by PID here.
 
 
-use super::TaskControlBlock;
-use crate::sync::UPSafeCell;
-use alloc::collections::VecDeque;
+use super::scheduler::{EdfScheduler, RtParams, Scheduler, SelectedScheduler};
+use super::processor::wake_idle_hart;
+use super::{ProcessControlBlock, TaskControlBlock};
+use crate::sync::SpinNoIrqLock;
+use crate::timer::get_time_us;
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
 
+/// The ready queue
+///
+/// Real-time threads are scheduled by EDF and always run before normal
+/// threads, which are scheduled by the policy chosen at build time.
 pub struct TaskManager {
-    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+    rt: EdfScheduler,
+    normal: SelectedScheduler,
+}
+
+fn is_realtime(task: &Arc<TaskControlBlock>) -> bool {
+    task.inner_exclusive_access().sched.rt.is_some()
 }
 
-// YOUR JOB: FIFO->Stride
-/// A simple FIFO scheduler.
 impl TaskManager {
     pub fn new() -> Self {
         Self {
-            ready_queue: VecDeque::new(),
+            rt: EdfScheduler::new(),
+            normal: SelectedScheduler::new(),
         }
     }
-    /// Add process back to ready queue
+    /// Add thread back to ready queue
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
-        self.ready_queue.push_back(task);
+        if is_realtime(&task) {
+            self.rt.add(task);
+        } else {
+            self.normal.add(task);
+        }
     }
-    /// Take a process out of the ready queue
+    /// Take the next thread to run out of the ready queue
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        self.ready_queue.pop_front()
+        self.rt.fetch().or_else(|| self.normal.fetch())
+    }
+    /// Remove a thread from the ready queue if it is there
+    pub fn remove(&mut self, task: &Arc<TaskControlBlock>) {
+        self.rt.remove(task);
+        self.normal.remove(task);
+    }
+    pub fn on_tick(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        self.rt.replenish();
+        if is_realtime(task) {
+            self.rt.on_tick(task)
+        } else {
+            // real-time threads preempt normal ones right away
+            self.normal.on_tick(task) || self.rt.has_ready()
+        }
+    }
+    pub fn on_block(&mut self, task: &Arc<TaskControlBlock>) {
+        if is_realtime(task) {
+            self.rt.on_block(task);
+        } else {
+            self.normal.on_block(task);
+        }
     }
 }
 
 lazy_static! {
     /// TASK_MANAGER instance through lazy_static!
-    pub static ref TASK_MANAGER: UPSafeCell<TaskManager> =
-        unsafe { UPSafeCell::new(TaskManager::new()) };
+    pub static ref TASK_MANAGER: SpinNoIrqLock<TaskManager> =
+        SpinNoIrqLock::new(TaskManager::new());
+    /// Live processes indexed by PID
+    pub static ref PID2PCB: SpinNoIrqLock<BTreeMap<usize, Arc<ProcessControlBlock>>> =
+        SpinNoIrqLock::new(BTreeMap::new());
 }
 
 pub fn add_task(task: Arc<TaskControlBlock>) {
-    TASK_MANAGER.exclusive_access().add(task);
+    TASK_MANAGER.lock().add(task);
+    wake_idle_hart();
 }
 
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
-    TASK_MANAGER.exclusive_access().fetch()
+    TASK_MANAGER.lock().fetch()
+}
+
+/// When a throttled real-time thread becomes ready again, if there is one
+pub fn next_replenish_us() -> Option<usize> {
+    TASK_MANAGER.lock().rt.next_replenish_us()
+}
+
+pub fn remove_task(task: Arc<TaskControlBlock>) {
+    TASK_MANAGER.lock().remove(&task);
+}
+
+/// Account a timer tick to the running thread, return whether to preempt it
+pub fn tick_task(task: &Arc<TaskControlBlock>) -> bool {
+    TASK_MANAGER.lock().on_tick(task)
+}
+
+/// Tell the scheduler the running thread is about to block
+pub fn block_task(task: &Arc<TaskControlBlock>) {
+    TASK_MANAGER.lock().on_block(task);
+}
+
+pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
+    let map = PID2PCB.lock();
+    map.get(&pid).map(Arc::clone)
+}
+
+/// Live processes in process group `pgid`
+///
+/// The PCBs are locked one at a time after PID2PCB is released, as PID2PCB
+/// may be locked while holding a PCB.
+pub fn pgid2processes(pgid: usize) -> Vec<Arc<ProcessControlBlock>> {
+    let processes: Vec<_> = PID2PCB.lock().values().cloned().collect();
+    processes
+        .into_iter()
+        .filter(|process| {
+            let inner = process.inner_exclusive_access();
+            !inner.is_zombie() && inner.pgid == pgid
+        })
+        .collect()
+}
+
+pub fn insert_into_pid2process(pid: usize, process: Arc<ProcessControlBlock>) {
+    PID2PCB.lock().insert(pid, process);
+}
+
+pub fn remove_from_pid2process(pid: usize) {
+    let mut map = PID2PCB.lock();
+    if map.remove(&pid).is_none() {
+        panic!("cannot find pid {} in pid2process!", pid);
+    }
+}
+
+/// Make `task`, which must not be in the ready queue, a real-time thread
+/// with the given parameters in microseconds, or a normal one again if
+/// `period` is 0. Return false if the parameters are invalid or do not
+/// pass the admission test.
+pub fn set_realtime(
+    task: &Arc<TaskControlBlock>,
+    period: usize,
+    runtime: usize,
+    deadline: usize,
+) -> bool {
+    let mut manager = TASK_MANAGER.lock();
+    let mut task_inner = task.inner_exclusive_access();
+    if period == 0 {
+        if let Some(old) = task_inner.sched.rt.take() {
+            manager.rt.release(&old);
+        }
+        return true;
+    }
+    let params = match RtParams::new(period, runtime, deadline) {
+        Some(params) => params,
+        None => return false,
+    };
+    if !manager.rt.admit(task_inner.sched.rt.as_ref(), &params) {
+        return false;
+    }
+    task_inner.sched.rt = Some(params);
+    // the budget of the first period is counted from now on
+    task_inner.sched.exec_start_us = get_time_us();
+    true
+}
+
+/// Give back the bandwidth of an exiting real-time thread
+pub fn release_realtime(task: &Arc<TaskControlBlock>) {
+    // TASK_MANAGER is always locked before a TCB
+    let params = task.inner_exclusive_access().sched.rt.take();
+    if let Some(params) = params {
+        TASK_MANAGER.lock().rt.release(&params);
+    }
 }
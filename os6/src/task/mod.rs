@@ -1,38 +1,55 @@
-//! Implementation of process management mechanism
+//! Implementation of process and thread management mechanism
 //!
-//! Here is the entry for process scheduling required by other modules
+//! Here is the entry for scheduling required by other modules
 //! (such as syscall or clock interrupt).
-//! By suspending or exiting the current process, you can
-//! modify the process state, manage the process queue through TASK_MANAGER,
-//! and switch the control flow through PROCESSOR.
+//! A process ([`ProcessControlBlock`]) owns the address space and the fd
+//! table, while the threads ([`TaskControlBlock`]) inside it are what gets
+//! scheduled. By suspending or exiting the current thread, you can
+//! modify the thread state, manage the ready queue through TASK_MANAGER,
+//! and switch the control flow through the Processor of the current hart.
 //!
 //! Be careful when you see [`__switch`]. Control flow around this function
 //! might not be what you expect.
 
 mod context;
+mod id;
+mod mailbox;
 mod manager;
-mod pid;
+mod process;
 mod processor;
+mod scheduler;
+mod signal;
+mod swap;
 mod switch;
 #[allow(clippy::module_inception, unused_imports)]
 mod task;
-use crate::{mm::{VirtPageNum, MapPermission, VirtAddr}, console::print};
+use crate::{mm::{FileMapping, PageFaultError, PageFaultKind, VirtPageNum, MapPermission, VirtAddr, VPNRange}, console::print};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
 use manager::fetch_task;
 use switch::__switch;
 use crate::config::PAGE_SIZE;
-use crate::timer::get_time_us;
+use crate::timer::{get_time_us, remove_timer};
 pub use crate::syscall::process::TaskInfo;
 use crate::fs::{open_file, OpenFlags};
-pub use task::{TaskControlBlock, TaskStatus};
+pub use task::{TaskControlBlock, TaskStatus, WaitState};
+use id::TaskUserRes;
+use manager::{block_task, release_realtime, remove_from_pid2process, remove_task, tick_task};
 
 pub use context::TaskContext;
-pub use manager::add_task;
-pub use pid::{pid_alloc, KernelStack, PidHandle};
+pub use id::{kstack_alloc, pid_alloc, KernelStack, PidHandle};
+pub use manager::{add_task, pgid2processes, pid2process, set_realtime};
+pub use process::ProcessControlBlock;
+pub use signal::{
+    current_signal_pending, handle_signals, send_fault_signal, send_group_signal, send_signal,
+    SignalAction, SignalFlags,
+};
 pub use processor::{
-    current_task, current_trap_cx, current_user_token, run_tasks, schedule, take_current_task,
+    current_process, current_task, current_trap_cx, current_trap_cx_user_va, current_user_token,
+    hart_id, run_tasks, schedule, take_current_task,
 };
+pub use swap::reclaim_frames;
 
 /// Make current task suspended and switch to the next task
 pub fn suspend_current_and_run_next() {
@@ -44,6 +61,8 @@ pub fn suspend_current_and_run_next() {
     let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
     // Change status to Ready
     task_inner.task_status = TaskStatus::Ready;
+    // charge the time it has run to its vruntime and budget
+    task_inner.sched.account_runtime();
     drop(task_inner);
     // ---- release current PCB
 
@@ -53,62 +72,278 @@ pub fn suspend_current_and_run_next() {
     schedule(task_cx_ptr);
 }
 
-/// Exit current task, recycle process resources and switch to the next task
+/// Mark the current thread as waiting for an event, before putting it where
+/// the event wakes it up. Unless `interruptible`, a signal does not.
+pub fn set_current_waiting(interruptible: bool) {
+    current_task().unwrap().inner_exclusive_access().wait_state = if interruptible {
+        WaitState::Interruptible
+    } else {
+        WaitState::Uninterruptible
+    };
+}
+
+/// Make current thread wait and switch to the next one, and return false if
+/// a signal interrupted the wait
+///
+/// The caller is responsible for keeping the thread somewhere, e.g. in the
+/// wait queue of a mutex, so that it can be woken up with [`wakeup_task`],
+/// and for marking it with [`set_current_waiting`] before. An interrupted
+/// thread is still kept there: it has to take itself out before waiting for
+/// anything else, lest the event wake it up from the wrong wait.
+pub fn block_current_and_run_next() -> bool {
+    // a signal sent before we were marked as waiting did not wake us up
+    let task = current_task().unwrap();
+    let interruptible = task.inner_exclusive_access().wait_state == WaitState::Interruptible;
+    if interruptible && current_signal_pending() {
+        interrupt_task(&task);
+    }
+    drop(task);
+    let task = take_current_task().unwrap();
+    block_task(&task);
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    // another hart may have woken us up already since we were put into the
+    // wait queue, in which case we are in the ready queue and only yield
+    if task_inner.task_status == TaskStatus::Running {
+        task_inner.task_status = TaskStatus::Blocked;
+    }
+    task_inner.sched.account_runtime();
+    drop(task_inner);
+    schedule(task_cx_ptr);
+    let mut task_inner = task.inner_exclusive_access();
+    let interrupted = task_inner.wait_state == WaitState::Interrupted;
+    task_inner.wait_state = WaitState::None;
+    !interrupted
+}
+
+/// Put a thread blocked by [`block_current_and_run_next`] back to the ready
+/// queue, and return false if it is not waiting any more, e.g. interrupted
+/// by a signal, in which case it is left alone
+///
+/// The thread may not have blocked yet if it is on another hart.
+pub fn wakeup_task(task: Arc<TaskControlBlock>) -> bool {
+    let mut task_inner = task.inner_exclusive_access();
+    if !matches!(
+        task_inner.wait_state,
+        WaitState::Uninterruptible | WaitState::Interruptible
+    ) {
+        return false;
+    }
+    assert!(
+        task_inner.task_status == TaskStatus::Blocked
+            || task_inner.task_status == TaskStatus::Running
+    );
+    task_inner.wait_state = WaitState::None;
+    task_inner.task_status = TaskStatus::Ready;
+    drop(task_inner);
+    add_task(task);
+    true
+}
+
+/// Wake `task` up if it waits interruptibly, see [`block_current_and_run_next`]
+fn interrupt_task(task: &Arc<TaskControlBlock>) {
+    let mut task_inner = task.inner_exclusive_access();
+    if task_inner.wait_state != WaitState::Interruptible {
+        return;
+    }
+    task_inner.wait_state = WaitState::Interrupted;
+    task_inner.task_status = TaskStatus::Ready;
+    drop(task_inner);
+    add_task(Arc::clone(task));
+}
+
+/// Account a timer tick to the current thread and return whether the
+/// scheduler wants to preempt it
+pub fn tick_current() -> bool {
+    tick_task(&current_task().unwrap())
+}
+
+/// Exit current thread and switch to the next one
+///
+/// If it is the main thread, the whole process exits: its other threads are
+/// taken off the ready queue and their user resources, as well as the
+/// address space and fd table, are recycled right away. The PCB lingers as a
+/// zombie until the parent reaps it.
 pub fn exit_current_and_run_next(exit_code: i32) {
+    exit_current(exit_code, false);
+}
+
+/// Exit the whole process of the current thread, which need not be the main
+/// one, e.g. when it is killed by a signal
+pub fn exit_process_and_run_next(exit_code: i32) {
+    exit_current(exit_code, true);
+}
+
+fn exit_current(exit_code: i32, whole_process: bool) {
     // take from Processor
     let task = take_current_task().unwrap();
-    // **** access current TCB exclusively
-    let mut inner = task.inner_exclusive_access();
-    // Change status to Zombie
-    inner.task_status = TaskStatus::Zombie;
-    // Record exit code
-    inner.exit_code = exit_code;
-    // do not move to its parent but under initproc
-
-    // ++++++ access initproc TCB exclusively
-    {
-        let mut initproc_inner = INITPROC.inner_exclusive_access();
-        for child in inner.children.iter() {
-            child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
-            initproc_inner.children.push(child.clone());
+    let mut task_inner = task.inner_exclusive_access();
+    let process = task.process.upgrade().unwrap();
+    // the user resources are gone if the main thread recycled them, which
+    // only happens to other threads
+    let is_main = task_inner.res.as_ref().map_or(false, |res| res.tid == 0);
+    // record exit code
+    task_inner.exit_code = Some(exit_code);
+    task_inner.task_status = TaskStatus::Zombie;
+    // recycling the user resources needs the PCB, which must not be locked
+    // while holding the TCB
+    let res = task_inner.res.take();
+    // the thread is not removed from the process here since we are still
+    // using its kernel stack; it goes away when sys_waittid reaps it
+    drop(task_inner);
+    drop(res);
+    release_realtime(&task);
+    // **** access current PCB exclusively
+    let mut process_inner = process.inner_exclusive_access();
+    // the process dies along with its main thread, unless another thread
+    // has torn it down already
+    if (is_main || whole_process) && !process_inner.is_zombie {
+        // mark this process as a zombie process, so that its threads running
+        // on other harts exit on their way back to user space
+        process_inner.is_zombie = true;
+        // record exit code of main thread
+        process_inner.exit_code = exit_code;
+        let children = core::mem::take(&mut process_inner.children);
+        let tasks: Vec<_> = process_inner
+            .tasks
+            .iter()
+            .flatten()
+            .filter(|t| !Arc::ptr_eq(t, &task))
+            .cloned()
+            .collect();
+        // read along with setting is_zombie, so that we wake up initproc
+        // instead if our parent hands us over to it in the meantime
+        let parent = process_inner.parent.as_ref().and_then(|parent| parent.upgrade());
+        drop(process_inner);
+        // **** release current PCB
+        // nobody can look this process up any longer
+        remove_from_pid2process(process.getpid());
+
+        // the parent may be blocked in waitpid
+        if let Some(parent) = parent {
+            parent.inner_exclusive_access().child_exit.wake_all();
         }
-    }
-    // ++++++ release parent PCB
 
-    inner.children.clear();
-    // deallocate user space
-    inner.memory_set.recycle_data_pages();
-    drop(inner);
-    // **** release current PCB
-    // drop task manually to maintain rc correctly
+        // do not move to its parent but under initproc; a PCB is never
+        // locked while holding another one, since the parent locks its
+        // children in waitpid
+        if !children.is_empty() {
+            for child in children.iter() {
+                child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
+            }
+            let mut initproc_inner = INITPROC.inner_exclusive_access();
+            initproc_inner.children.extend(children);
+            // some of them may be zombies already
+            initproc_inner.child_exit.wake_all();
+        }
+
+        // other threads may still be waiting in the ready queue, or sleeping
+        // on a timer, or running on other harts. Wait for the last ones to
+        // leave before their address space goes away.
+        for task in tasks.iter() {
+            // nothing may wake them up any more
+            task.inner_exclusive_access().wait_state = WaitState::None;
+            remove_timer(Arc::clone(task));
+            remove_task(Arc::clone(task));
+            release_realtime(task);
+        }
+        for task in tasks.iter() {
+            while task.inner_exclusive_access().on_cpu {
+                core::hint::spin_loop();
+            }
+        }
+        // recycle user resources of all threads: it has to happen before the
+        // whole memory_set goes away, otherwise they would be deallocated twice
+        for task in tasks.iter() {
+            let res: Option<TaskUserRes> = task.inner_exclusive_access().res.take();
+            // recycling them locks the PCB
+            drop(res);
+        }
+        drop(tasks);
+
+        let mut process_inner = process.inner_exclusive_access();
+        // deallocate user space
+        process_inner.memory_set.recycle_data_pages();
+        // drop file descriptors
+        process_inner.fd_table.clear();
+        // drop synchronization primitives along with threads blocked on them
+        process_inner.mutex_list.clear();
+        process_inner.semaphore_list.clear();
+        process_inner.condvar_list.clear();
+        // keep the main thread only: we may still be running on its kernel
+        // stack, which is freed when the parent reaps this process
+        while process_inner.tasks.len() > 1 {
+            process_inner.tasks.pop();
+        }
+        // **** release current PCB
+    } else {
+        drop(process_inner);
+    }
     drop(task);
+    drop(process);
     // we do not have to save task context
     let mut _unused = TaskContext::zero_init();
     schedule(&mut _unused as *mut _);
 }
 
+/// Handle a page fault of the current process at `va`, caused by an access
+/// of `kind`, and return false if the access is not allowed, or if no frame
+/// could be reclaimed for the page either
+pub fn handle_page_fault(va: usize, kind: PageFaultKind) -> bool {
+    // the page may have to be swapped in
+    reclaim_frames();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    match inner.memory_set.handle_page_fault(VirtAddr::from(va).floor(), kind) {
+        Ok(()) => return true,
+        Err(PageFaultError::Denied) => return false,
+        Err(PageFaultError::NoFrame) => {}
+    }
+    // the frames kept free ran out, e.g. taken by other harts, so reclaim
+    // some more without holding current PCB, and try once again
+    drop(inner);
+    reclaim_frames();
+    let mut inner = process.inner_exclusive_access();
+    inner
+        .memory_set
+        .handle_page_fault(VirtAddr::from(va).floor(), kind)
+        .is_ok()
+}
+
+/// Mark whether the current thread is inside a syscall, see [`reclaim_frames`]
+pub fn set_current_in_syscall(in_syscall: bool) {
+    current_task().unwrap().inner_exclusive_access().in_syscall = in_syscall;
+}
+
+/// Whether the process of the current thread has exited, in which case the
+/// thread must not go back to user space
+pub fn current_process_exited() -> bool {
+    current_process().inner_exclusive_access().is_zombie()
+}
+
 lazy_static! {
     /// Creation of initial process
     ///
     /// the name "initproc" may be changed to any other app name like "usertests",
     /// but we have user_shell, so we don't need to change it.
-    pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new({
+    pub static ref INITPROC: Arc<ProcessControlBlock> = {
         let inode = open_file("ch6b_initproc", OpenFlags::RDONLY).unwrap();
         let v = inode.read_all();
-        TaskControlBlock::new(v.as_slice())
-    });
+        ProcessControlBlock::new(v.as_slice())
+    };
 }
 
+/// Create initproc, whose main thread is put into the ready queue on creation
 pub fn add_initproc() {
-    add_task(INITPROC.clone());
+    let _initproc = INITPROC.clone();
 }
 
 // use crate::config::PAGE_SIZE;
 #[allow(dead_code, unused_variables, unused)]
-pub fn mmap(start: usize, len: usize, port: usize) -> isize{
+pub fn mmap(start: usize, len: usize, port: usize, file: Option<FileMapping>) -> isize{
     // println!("1");
-    let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
 
     // println!("2");
     let start_va = VirtPageNum::from(start / PAGE_SIZE);
@@ -118,7 +353,6 @@ pub fn mmap(start: usize, len: usize, port: usize) -> isize{
     for vpn in start_va.0..end_va.0 {
         if inner.memory_set.find_vpn(VirtPageNum(vpn)) {
             // println!("{}  {}", start / PAGE_SIZE, (start + len) / PAGE_SIZE);
-            println!("there is a overlap!!!");
             return -1;
         }
     }
@@ -127,7 +361,10 @@ pub fn mmap(start: usize, len: usize, port: usize) -> isize{
     let permission = MapPermission::from_bits(((port << 1) | 16) as u8);
     
     // println!("5");
-    inner.memory_set.insert_framed_area(VirtAddr::from(start_va), VirtAddr::from(end_va), permission.unwrap());
+    match file {
+        Some(file) => inner.memory_set.insert_file_area(VirtAddr::from(start_va), VirtAddr::from(end_va), permission.unwrap(), file),
+        None => inner.memory_set.insert_framed_area(VirtAddr::from(start_va), VirtAddr::from(end_va), permission.unwrap()),
+    }
     // inner.memory_set.insert_framed_area(VirtAddr::from(start), VirtAddr::from(start+len), permission.unwrap());
 
     // println!("6");
@@ -144,8 +381,8 @@ pub fn mmap(start: usize, len: usize, port: usize) -> isize{
 pub fn unmmap(start: usize, len: usize) -> isize {
     // println!("inside unmmap function !!!") ;
     // println!("A1");
-    let task = current_task().unwrap();
-    let mut inner = task.inner_exclusive_access();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
 
     // println!("A2");
     let start_va = VirtPageNum::from(start / PAGE_SIZE);
@@ -154,14 +391,28 @@ pub fn unmmap(start: usize, len: usize) -> isize {
     // println!("A");
     for vpn in start_va.0..end_va.0 { 
         if !(inner.memory_set.find_vpn(VirtPageNum(vpn))) {
-            println!("it seem couldn't find the pte");
             return -1;
         }
     }
     // println!("B");
-    for vpn in start_va.0..end_va.0 {
-        inner.memory_set.delete_pte_from(VirtPageNum(vpn));
-    }
+    inner.memory_set.remove_range(start_va, end_va);
     // println!("successed!!!");
     0
 }
+
+/// Write the shared file mappings in `[start, start + len)` back to their
+/// files, return -1 if some page there is not mapped
+pub fn msync(start: usize, len: usize) -> isize {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let start_vpn = VirtAddr::from(start).floor();
+    let end_vpn = VirtAddr::from(start + len).ceil();
+    let unmapped = VPNRange::new(start_vpn, end_vpn)
+        .into_iter()
+        .any(|vpn| !inner.memory_set.find_vpn(vpn));
+    if unmapped {
+        return -1;
+    }
+    inner.memory_set.sync_range(start_vpn, end_vpn);
+    0
+}
@@ -0,0 +1,390 @@
+//! Types related to process management & Functions for completely changing PCB
+
+use super::add_task;
+use super::manager::insert_into_pid2process;
+use super::mailbox::MailBox;
+use super::signal::{SignalActions, SignalFlags};
+use super::id::RecycleAllocator;
+use super::{pid_alloc, PidHandle, TaskControlBlock};
+use crate::fs::{File, Stdin, Stdout};
+use crate::mm::{translated_refmut, MemorySet, KERNEL_SPACE};
+use crate::sync::{
+    Condvar, DeadlockDetector, Mutex, Semaphore, SpinNoIrqLock, SpinNoIrqLockGuard, WaitQueue,
+};
+use crate::trap::{trap_handler, TrapContext};
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Process control block structure
+///
+/// Owns the resources shared by all threads of a process
+pub struct ProcessControlBlock {
+    // immutable
+    /// Process identifier
+    pub pid: PidHandle,
+    // mutable
+    inner: SpinNoIrqLock<ProcessControlBlockInner>,
+}
+
+/// Structure containing more process content
+///
+/// Store the contents that will change during operation
+/// and are wrapped by SpinNoIrqLock to provide mutual exclusion
+pub struct ProcessControlBlockInner {
+    /// Set once the main thread exits
+    pub is_zombie: bool,
+    /// Application address space
+    pub memory_set: MemorySet,
+    /// Application data can only appear in areas
+    /// where the application address space is lower than base_size
+    pub base_size: usize,
+    /// Parent process of the current process.
+    /// Weak will not affect the reference count of the parent
+    pub parent: Option<Weak<ProcessControlBlock>>,
+    /// A vector containing PCBs of all child processes of the current process
+    pub children: Vec<Arc<ProcessControlBlock>>,
+    /// Threads blocked in waitpid until some child exits
+    pub child_exit: WaitQueue,
+    /// Exit code of the main thread
+    pub exit_code: i32,
+    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    /// Threads of this process, indexed by TID
+    pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
+    /// TID allocator
+    pub task_res_allocator: RecycleAllocator,
+    /// Mutexes created by the threads of this process, indexed by mutex id
+    pub mutex_list: Vec<Option<Arc<dyn Mutex>>>,
+    /// Semaphores created by the threads of this process, indexed by semaphore id
+    pub semaphore_list: Vec<Option<Arc<Semaphore>>>,
+    /// Condition variables created by the threads of this process, indexed by condvar id
+    pub condvar_list: Vec<Option<Arc<Condvar>>>,
+    /// Whether lock and down requests that may deadlock are refused
+    pub deadlock_detect: bool,
+    /// Banker's algorithm bookkeeping of mutexes
+    pub mutex_detector: DeadlockDetector,
+    /// Banker's algorithm bookkeeping of semaphores
+    pub semaphore_detector: DeadlockDetector,
+    /// Messages sent to this process by sys_mail_write
+    pub mailbox: MailBox,
+    /// Signals sent to this process but not delivered yet
+    pub signals: SignalFlags,
+    /// Signals blocked from delivery
+    pub signal_mask: SignalFlags,
+    /// Handlers set by sigaction
+    pub signal_actions: SignalActions,
+    /// Stopped by a signal until SIGCONT comes
+    pub stopped: bool,
+    /// Threads blocked while the process is stopped
+    pub stop_queue: WaitQueue,
+    /// Signal which stopped the process, until reported to the parent by
+    /// waitpid with WUNTRACED
+    pub stop_report: Option<usize>,
+    /// Process group, whose id is the pid of the process which created it
+    pub pgid: usize,
+}
+
+/// Simple access to its internal fields
+impl ProcessControlBlockInner {
+    pub fn get_user_token(&self) -> usize {
+        self.memory_set.token()
+    }
+    pub fn is_zombie(&self) -> bool {
+        self.is_zombie
+    }
+    pub fn alloc_fd(&mut self) -> usize {
+        if let Some(fd) = (0..self.fd_table.len())
+            .find(|fd| self.fd_table[*fd].is_none()) {
+            fd
+        } else {
+            self.fd_table.push(None);
+            self.fd_table.len() - 1
+        }
+    }
+    pub fn alloc_tid(&mut self) -> usize {
+        self.task_res_allocator.alloc()
+    }
+    pub fn dealloc_tid(&mut self, tid: usize) {
+        self.task_res_allocator.dealloc(tid)
+    }
+    /// Threads not waited for yet, the slots of those that were being left
+    /// empty in `tasks`
+    pub fn thread_count(&self) -> usize {
+        self.tasks.iter().filter(|task| task.is_some()).count()
+    }
+    pub fn get_task(&self, tid: usize) -> Arc<TaskControlBlock> {
+        self.tasks[tid].as_ref().unwrap().clone()
+    }
+}
+
+impl ProcessControlBlock {
+    /// Lock ProcessControlBlockInner of this block
+    pub fn inner_exclusive_access(&self) -> SpinNoIrqLockGuard<'_, ProcessControlBlockInner> {
+        self.inner.lock()
+    }
+
+    /// Create a new process with a main thread ready to run
+    ///
+    /// At present, it is only used for the creation of initproc
+    pub fn new(elf_data: &[u8]) -> Arc<Self> {
+        Self::create_from_elf(elf_data, None)
+    }
+
+    /// Create a new process with a main thread, without copying anything from
+    /// the parent. The main thread is added to the scheduler.
+    fn create_from_elf(elf_data: &[u8], parent: Option<&Arc<Self>>) -> Arc<Self> {
+        // memory_set with elf program headers/trampoline
+        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+        // alloc a pid
+        let pid_handle = pid_alloc();
+        // initproc makes up the first process group, every other process
+        // starts in the group of its parent
+        let pgid = parent.map_or(pid_handle.0, |parent| parent.inner_exclusive_access().pgid);
+        let process = Arc::new(Self {
+            pid: pid_handle,
+            inner: SpinNoIrqLock::new(ProcessControlBlockInner {
+                is_zombie: false,
+                memory_set,
+                base_size: ustack_base,
+                parent: parent.map(Arc::downgrade),
+                children: Vec::new(),
+                child_exit: WaitQueue::new(),
+                exit_code: 0,
+                fd_table: vec![
+                    // 0 -> stdin
+                    Some(Arc::new(Stdin)),
+                    // 1 -> stdout
+                    Some(Arc::new(Stdout)),
+                    // 2 -> stderr
+                    Some(Arc::new(Stdout)),
+                ],
+                tasks: Vec::new(),
+                task_res_allocator: RecycleAllocator::new(),
+                mutex_list: Vec::new(),
+                semaphore_list: Vec::new(),
+                condvar_list: Vec::new(),
+                deadlock_detect: false,
+                mutex_detector: DeadlockDetector::new(),
+                semaphore_detector: DeadlockDetector::new(),
+                mailbox: MailBox::new(),
+                signals: SignalFlags::empty(),
+                signal_mask: SignalFlags::empty(),
+                signal_actions: SignalActions::new(),
+                stopped: false,
+                stop_queue: WaitQueue::new(),
+                stop_report: None,
+                pgid,
+            }),
+        });
+        insert_into_pid2process(process.getpid(), Arc::clone(&process));
+        // create the main thread, user stack and trap context included
+        let task = Arc::new(TaskControlBlock::new(
+            Arc::clone(&process),
+            ustack_base,
+            true,
+        ));
+        // prepare TrapContext of the main thread
+        let task_inner = task.inner_exclusive_access();
+        let trap_cx = task_inner.get_trap_cx();
+        let ustack_top = task_inner.res.as_ref().unwrap().ustack_top();
+        let kstack_top = task.kstack.get_top();
+        drop(task_inner);
+        *trap_cx = TrapContext::app_init_context(
+            entry_point,
+            ustack_top,
+            KERNEL_SPACE.lock().token(),
+            kstack_top,
+            trap_handler as usize,
+        );
+        // attach the main thread to the process
+        process
+            .inner_exclusive_access()
+            .tasks
+            .push(Some(Arc::clone(&task)));
+        if let Some(parent) = parent {
+            parent
+                .inner_exclusive_access()
+                .children
+                .push(Arc::clone(&process));
+        }
+        add_task(task);
+        process
+    }
+
+    /// Create a child process running `elf_data` directly, without copying
+    /// the address space of the parent the way fork does
+    pub fn spawn(self: &Arc<Self>, elf_data: &[u8]) -> Arc<Self> {
+        Self::create_from_elf(elf_data, Some(self))
+    }
+
+    /// Load a new elf to replace the original application address space and start execution
+    ///
+    /// `args` are copied onto the top of the new user stack: a
+    /// null-terminated argv array first, then the strings themselves below
+    /// it. argc and argv are handed to the program in a0/a1.
+    ///
+    /// Only support processes with a single thread, return false for others.
+    pub fn exec(self: &Arc<Self>, elf_data: &[u8], args: Vec<String>) -> bool {
+        if self.inner_exclusive_access().thread_count() != 1 {
+            return false;
+        }
+        // memory_set with elf program headers/trampoline
+        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+        let token = memory_set.token();
+        // substitute memory_set
+        let mut inner = self.inner_exclusive_access();
+        inner.memory_set = memory_set;
+        inner.base_size = ustack_base;
+        // the handlers are gone along with the old code
+        inner.signal_actions.reset_handlers();
+        let task = inner.get_task(0);
+        drop(inner);
+        // the user stack and trap context of the main thread went away with
+        // the old memory_set, so map them again in the new one. Mapping
+        // them locks the PCB, which must not happen while holding the TCB.
+        let mut res = task.inner_exclusive_access().res.take().unwrap();
+        res.ustack_base = ustack_base;
+        res.alloc_user_res();
+        let trap_cx_ppn = res.trap_cx_ppn();
+        let mut user_sp = res.ustack_top();
+        // push argv pointers (with a trailing null) on user stack, whose
+        // pages are mapped on the first write, which locks the PCB as well
+        // so it must come before locking the TCB
+        user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
+        let argv_base = user_sp;
+        let mut argv: Vec<_> = (0..=args.len())
+            .map(|arg| {
+                translated_refmut(
+                    token,
+                    (argv_base + arg * core::mem::size_of::<usize>()) as *mut usize,
+                )
+                .unwrap()
+            })
+            .collect();
+        *argv[args.len()] = 0;
+        // push argument strings below argv
+        for i in 0..args.len() {
+            user_sp -= args[i].len() + 1;
+            *argv[i] = user_sp;
+            let mut p = user_sp;
+            for c in args[i].as_bytes() {
+                *translated_refmut(token, p as *mut u8).unwrap() = *c;
+                p += 1;
+            }
+            *translated_refmut(token, p as *mut u8).unwrap() = 0;
+        }
+        // keep user_sp aligned to 8 bytes
+        user_sp -= user_sp % core::mem::size_of::<usize>();
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.res = Some(res);
+        task_inner.trap_cx_ppn = trap_cx_ppn;
+        task_inner.handling_sig = None;
+        task_inner.trap_cx_backup = None;
+        // initialize trap_cx
+        let trap_cx = task_inner.get_trap_cx();
+        *trap_cx = TrapContext::app_init_context(
+            entry_point,
+            user_sp,
+            KERNEL_SPACE.lock().token(),
+            task.kstack.get_top(),
+            trap_handler as usize,
+        );
+        trap_cx.x[10] = args.len();
+        trap_cx.x[11] = argv_base;
+        true
+    }
+
+    /// Fork from parent to child
+    ///
+    /// Only support processes with a single thread, return None for others.
+    pub fn fork(self: &Arc<Self>) -> Option<Arc<Self>> {
+        // ---- access parent PCB exclusively
+        let mut parent_inner = self.inner_exclusive_access();
+        if parent_inner.thread_count() != 1 {
+            return None;
+        }
+        // copy user space(include user stacks and trap contexts), sharing
+        // the pages of the user copy-on-write
+        let memory_set = MemorySet::from_existed_user(&mut parent_inner.memory_set);
+        // alloc a pid
+        let pid_handle = pid_alloc();
+        let mut new_fd_table: Vec<Option<Arc<dyn File + Send + Sync>>> = Vec::new();
+        // clone all fds from parent to child
+        for fd in parent_inner.fd_table.iter() {
+            if let Some(file) = fd {
+                new_fd_table.push(Some(file.clone()));
+            } else {
+                new_fd_table.push(None);
+            }
+        }
+        let child = Arc::new(Self {
+            pid: pid_handle,
+            inner: SpinNoIrqLock::new(ProcessControlBlockInner {
+                is_zombie: false,
+                memory_set,
+                base_size: parent_inner.base_size,
+                parent: Some(Arc::downgrade(self)),
+                children: Vec::new(),
+                child_exit: WaitQueue::new(),
+                exit_code: 0,
+                fd_table: new_fd_table,
+                tasks: Vec::new(),
+                task_res_allocator: RecycleAllocator::new(),
+                mutex_list: Vec::new(),
+                semaphore_list: Vec::new(),
+                condvar_list: Vec::new(),
+                deadlock_detect: false,
+                mutex_detector: DeadlockDetector::new(),
+                semaphore_detector: DeadlockDetector::new(),
+                mailbox: MailBox::new(),
+                // handlers and the mask are inherited, pending signals are not
+                signals: SignalFlags::empty(),
+                signal_mask: parent_inner.signal_mask,
+                signal_actions: parent_inner.signal_actions.clone(),
+                stopped: false,
+                stop_queue: WaitQueue::new(),
+                stop_report: None,
+                pgid: parent_inner.pgid,
+            }),
+        });
+        // add child
+        parent_inner.children.push(Arc::clone(&child));
+        insert_into_pid2process(child.getpid(), Arc::clone(&child));
+        // create the main thread of child, whose user stack and trap context
+        // have been copied along with the address space; only the kernel
+        // stack is new
+        let ustack_base = parent_inner
+            .get_task(0)
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .ustack_base();
+        let task = Arc::new(TaskControlBlock::new(
+            Arc::clone(&child),
+            ustack_base,
+            false,
+        ));
+        child
+            .inner_exclusive_access()
+            .tasks
+            .push(Some(Arc::clone(&task)));
+        // modify kernel_sp in trap_cx
+        // **** access child TCB exclusively
+        let task_inner = task.inner_exclusive_access();
+        let trap_cx = task_inner.get_trap_cx();
+        trap_cx.kernel_sp = task.kstack.get_top();
+        // for child process, fork returns 0
+        trap_cx.x[10] = 0;
+        drop(task_inner);
+        // **** release child TCB
+        add_task(task);
+        Some(child)
+        // ---- release parent PCB automatically
+    }
+
+    pub fn getpid(&self) -> usize {
+        self.pid.0
+    }
+}
@@ -6,11 +6,20 @@
 
 
 use super::__switch;
+use super::manager::next_replenish_us;
 use super::{fetch_task, TaskStatus};
-use super::{TaskContext, TaskControlBlock};
-use crate::sync::UPSafeCell;
+use super::{ProcessControlBlock, TaskContext, TaskControlBlock};
+use crate::config::MAX_HART_NUM;
+use crate::fs::tty_has_readers;
+use crate::sbi::send_ipi;
+use crate::sync::SpinNoIrqLock;
+use crate::timer::{get_time_ms, get_time_us, next_tick_us, set_idle_trigger, set_next_trigger};
 use crate::trap::TrapContext;
 use alloc::sync::Arc;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use riscv::asm::wfi;
+use riscv::register::sstatus;
 use lazy_static::*;
 
 /// Processor management structure
@@ -40,50 +49,146 @@ impl Processor {
 }
 
 lazy_static! {
-    /// PROCESSOR instance through lazy_static!
-    pub static ref PROCESSOR: UPSafeCell<Processor> = unsafe { UPSafeCell::new(Processor::new()) };
+    /// One Processor for each hart, indexed by hart id
+    pub static ref PROCESSORS: [SpinNoIrqLock<Processor>; MAX_HART_NUM] =
+        [(); MAX_HART_NUM].map(|_| SpinNoIrqLock::new(Processor::new()));
 }
 
-/// The main part of process execution and scheduling
+/// Id of the hart we are running on, which is kept in tp while in kernel
+pub fn hart_id() -> usize {
+    let hart_id: usize;
+    unsafe {
+        core::arch::asm!("mv {}, tp", out(reg) hart_id);
+    }
+    hart_id
+}
+
+/// The Processor of the current hart
+fn current_processor() -> &'static SpinNoIrqLock<Processor> {
+    &PROCESSORS[hart_id()]
+}
+
+/// Harts sleeping in [`idle`], bit i for hart i
+static IDLE_HARTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Send an IPI to an idle hart, if any, since a thread has become ready
+pub fn wake_idle_hart() {
+    let idle_harts = IDLE_HARTS.load(Ordering::Acquire) & !(1 << hart_id());
+    if idle_harts != 0 {
+        // the lowest one is enough, as only one thread has been added
+        send_ipi(idle_harts & idle_harts.wrapping_neg());
+    }
+}
+
+/// Sleep with WFI until an interrupt comes, as there is nothing to run
+///
+/// Periodic timer ticks are skipped meanwhile: the timer is armed only for
+/// the next sleeping thread or real-time period to wake up. Threads made
+/// ready by other harts wake us up with an IPI. While some thread waits for
+/// the console, the timer keeps ticking to poll it. Return the thread fetched
+/// after announcing that we are idle, if there turns out to be one.
+fn idle() -> Option<Arc<TaskControlBlock>> {
+    let hart_mask = 1 << hart_id();
+    IDLE_HARTS.fetch_or(hart_mask, Ordering::AcqRel);
+    // a thread added before we showed up in IDLE_HARTS sent no IPI
+    let task = fetch_task();
+    if task.is_none() {
+        let mut deadline_us = next_replenish_us();
+        if tty_has_readers() {
+            let tick_us = next_tick_us();
+            deadline_us = Some(deadline_us.map_or(tick_us, |us| us.min(tick_us)));
+        }
+        set_idle_trigger(deadline_us);
+        unsafe {
+            // WFI returns on any pending interrupt even with SIE cleared,
+            // which is then taken once SIE is set
+            wfi();
+            sstatus::set_sie();
+            sstatus::clear_sie();
+        }
+    }
+    IDLE_HARTS.fetch_and(!hart_mask, Ordering::AcqRel);
+    // tick again for the thread we are about to run
+    set_next_trigger();
+    task
+}
+
+/// The main part of thread execution and scheduling
 ///
-/// Loop fetch_task to get the process that needs to run,
-/// and switch the process through __switch
+/// Loop fetch_task to get the thread that needs to run, and switch the
+/// thread through __switch. The hart sleeps in [`idle`] while nothing is
+/// ready to run.
 pub fn run_tasks() {
     loop {
-        let mut processor = PROCESSOR.exclusive_access();
-        if let Some(task) = fetch_task() {
-            let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
+        if let Some(task) = fetch_task().or_else(idle) {
+            // the hart it ran on last time may not have left its kernel
+            // stack yet, e.g. when it was woken up right before blocking
+            while task.inner_exclusive_access().on_cpu {
+                spin_loop();
+            }
+            let process = match task.process.upgrade() {
+                Some(process) => process,
+                None => continue,
+            };
+            // the PCB stays locked until the thread is on this hart, for the
+            // swapper to know which processes are running
+            let process_inner = process.inner_exclusive_access();
+            // a thread put back by itself while its process was exiting on
+            // another hart has nothing to go back to
+            if process_inner.is_zombie() {
+                continue;
+            }
             // access coming task TCB exclusively
             let mut task_inner = task.inner_exclusive_access();
+            if task_inner.task_status == TaskStatus::Blocked {
+                // it will be put back by whoever wakes it up
+                continue;
+            }
             let next_task_cx_ptr = &task_inner.task_cx as *const TaskContext;
             task_inner.task_status = TaskStatus::Running;
+            task_inner.on_cpu = true;
+            if task_inner.first_run_time.is_none() {
+                task_inner.first_run_time = Some(get_time_ms());
+            }
+            task_inner.sched.exec_start_us = get_time_us();
             drop(task_inner);
+            drop(process_inner);
+            drop(process);
             // release coming task TCB manually
-            processor.current = Some(task);
+            let mut processor = current_processor().lock();
+            let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
+            processor.current = Some(Arc::clone(&task));
             // release processor manually
             drop(processor);
             unsafe {
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
+            // back in idle control flow: the context of the task is saved,
+            // and our reference keeps its kernel stack alive until now
+            task.inner_exclusive_access().on_cpu = false;
         }
     }
 }
 
 /// Get current task through take, leaving a None in its place
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().take_current()
+    current_processor().lock().take_current()
 }
 
 /// Get a copy of the current task
 pub fn current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().current()
+    current_processor().lock().current()
+}
+
+/// Get the process the current task belongs to
+pub fn current_process() -> Arc<ProcessControlBlock> {
+    current_task().unwrap().process.upgrade().unwrap()
 }
 
 /// Get token of the address space of current task
 pub fn current_user_token() -> usize {
     let task = current_task().unwrap();
-    let token = task.inner_exclusive_access().get_user_token();
-    token
+    task.get_user_token()
 }
 
 /// Get the mutable reference to trap context of current task
@@ -94,9 +199,20 @@ pub fn current_trap_cx() -> &'static mut TrapContext {
         .get_trap_cx()
 }
 
+/// Get the virtual address of the trap context of current task in user space
+pub fn current_trap_cx_user_va() -> usize {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .trap_cx_user_va()
+}
+
 /// Return to idle control flow for new scheduling
 pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
-    let mut processor = PROCESSOR.exclusive_access();
+    let mut processor = current_processor().lock();
     let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
     drop(processor);
     unsafe {
@@ -0,0 +1,65 @@
+//! Completely fair scheduling
+
+use super::Scheduler;
+use crate::task::TaskControlBlock;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+
+/// CFS-like scheduler: the ready thread which has had the least weighted
+/// CPU time, its vruntime, runs next
+pub struct CfsScheduler {
+    /// Ready threads keyed by (vruntime, enqueue sequence number); the
+    /// sequence number tells apart threads with the same vruntime
+    ready_queue: BTreeMap<(usize, usize), Arc<TaskControlBlock>>,
+    /// Never decreasing lower bound of the vruntime of ready threads
+    min_vruntime: usize,
+    next_seq: usize,
+}
+
+impl Scheduler for CfsScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: BTreeMap::new(),
+            min_vruntime: 0,
+            next_seq: 0,
+        }
+    }
+    /// New threads, as well as threads coming back after blocking for a
+    /// while, start at min_vruntime so that they cannot hog the CPU
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let mut task_inner = task.inner_exclusive_access();
+        let vruntime = task_inner.sched.vruntime.max(self.min_vruntime);
+        task_inner.sched.vruntime = vruntime;
+        drop(task_inner);
+        self.ready_queue.insert((vruntime, self.next_seq), task);
+        self.next_seq += 1;
+    }
+    /// Take the thread with the smallest vruntime
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let key = *self.ready_queue.keys().next()?;
+        self.min_vruntime = self.min_vruntime.max(key.0);
+        self.ready_queue.remove(&key)
+    }
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) {
+        if let Some(key) = self
+            .ready_queue
+            .iter()
+            .find(|(_, t)| Arc::ptr_eq(t, task))
+            .map(|(key, _)| *key)
+        {
+            self.ready_queue.remove(&key);
+        }
+    }
+    /// Preempt the running thread once it is no longer the one with the
+    /// smallest vruntime
+    fn on_tick(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.sched.account_runtime();
+        let vruntime = task_inner.sched.vruntime;
+        drop(task_inner);
+        match self.ready_queue.keys().next() {
+            Some((min, _)) => *min < vruntime,
+            None => false,
+        }
+    }
+}
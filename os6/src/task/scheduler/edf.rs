@@ -0,0 +1,198 @@
+//! Earliest-deadline-first real-time scheduling class
+
+use super::Scheduler;
+use crate::task::TaskControlBlock;
+use crate::timer::get_time_us;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Fixed-point unit of CPU bandwidth: the whole CPU
+const FULL_BANDWIDTH: usize = 1_000_000;
+
+/// Real-time parameters of a thread, all in microseconds
+///
+/// In each period the thread may run for `runtime` and should have done
+/// so by `deadline` after the start of the period.
+pub struct RtParams {
+    pub period: usize,
+    pub runtime: usize,
+    pub deadline: usize,
+    /// Start of the current period
+    pub period_start: usize,
+    /// Absolute deadline of the current period
+    pub abs_deadline: usize,
+    /// Runtime left in the current period
+    pub budget: usize,
+}
+
+impl RtParams {
+    /// Parameters of a thread whose first period starts now.
+    /// Return None unless 0 < runtime <= deadline <= period.
+    pub fn new(period: usize, runtime: usize, deadline: usize) -> Option<Self> {
+        if runtime == 0 || runtime > deadline || deadline > period {
+            return None;
+        }
+        let mut params = Self {
+            period,
+            runtime,
+            deadline,
+            period_start: 0,
+            abs_deadline: 0,
+            budget: 0,
+        };
+        params.start_period(get_time_us());
+        Some(params)
+    }
+    fn start_period(&mut self, start: usize) {
+        self.period_start = start;
+        self.abs_deadline = start + self.deadline;
+        self.budget = self.runtime;
+    }
+    /// Share of the CPU the thread may claim, in units of [`FULL_BANDWIDTH`]
+    pub fn bandwidth(&self) -> usize {
+        self.runtime * FULL_BANDWIDTH / self.deadline
+    }
+}
+
+/// EDF scheduler for the threads that have [`RtParams`]
+///
+/// A thread which has used up its budget is throttled until its next
+/// period, so that it cannot overrun the bandwidth it has been admitted
+/// with.
+pub struct EdfScheduler {
+    /// Ready threads keyed by (absolute deadline, enqueue sequence number)
+    ready_queue: BTreeMap<(usize, usize), Arc<TaskControlBlock>>,
+    /// Threads out of budget, waiting for their next period
+    throttled: Vec<Arc<TaskControlBlock>>,
+    next_seq: usize,
+    /// Bandwidth claimed by admitted threads
+    bandwidth: usize,
+}
+
+impl EdfScheduler {
+    /// Admission test: accept a thread switching from `old` to `new`
+    /// parameters only if the total density stays within the CPU.
+    /// The bandwidth is claimed on success.
+    pub fn admit(&mut self, old: Option<&RtParams>, new: &RtParams) -> bool {
+        let released = old.map_or(0, RtParams::bandwidth);
+        let total = self.bandwidth - released + new.bandwidth();
+        if total > FULL_BANDWIDTH {
+            return false;
+        }
+        self.bandwidth = total;
+        true
+    }
+    /// Give back the bandwidth of a thread leaving the real-time class
+    pub fn release(&mut self, params: &RtParams) {
+        self.bandwidth -= params.bandwidth();
+    }
+    fn enqueue(&mut self, task: Arc<TaskControlBlock>, abs_deadline: usize) {
+        self.ready_queue.insert((abs_deadline, self.next_seq), task);
+        self.next_seq += 1;
+    }
+    /// Whether some real-time thread is ready to run
+    pub fn has_ready(&self) -> bool {
+        !self.ready_queue.is_empty()
+    }
+    /// When the first throttled thread gets its budget back, if there is one
+    pub fn next_replenish_us(&self) -> Option<usize> {
+        self.throttled
+            .iter()
+            .map(|task| {
+                let task_inner = task.inner_exclusive_access();
+                let params = task_inner.sched.rt.as_ref().unwrap();
+                params.period_start + params.period
+            })
+            .min()
+    }
+    /// Move throttled threads whose next period has come to the ready queue
+    pub fn replenish(&mut self) {
+        let now = get_time_us();
+        let mut i = 0;
+        while i < self.throttled.len() {
+            let mut task_inner = self.throttled[i].inner_exclusive_access();
+            let params = task_inner.sched.rt.as_mut().unwrap();
+            let next_start = params.period_start + params.period;
+            if next_start <= now {
+                // periods missed entirely are not made up for
+                if next_start + params.period <= now {
+                    params.start_period(now);
+                } else {
+                    params.start_period(next_start);
+                }
+                let abs_deadline = params.abs_deadline;
+                drop(task_inner);
+                let task = self.throttled.swap_remove(i);
+                self.enqueue(task, abs_deadline);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+impl Scheduler for EdfScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: BTreeMap::new(),
+            throttled: Vec::new(),
+            next_seq: 0,
+            bandwidth: 0,
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let now = get_time_us();
+        let mut task_inner = task.inner_exclusive_access();
+        let params = task_inner.sched.rt.as_mut().unwrap();
+        if params.budget == 0 {
+            drop(task_inner);
+            self.throttled.push(task);
+            return;
+        }
+        // coming back after blocking: keep the current deadline only if
+        // running out the budget left before it does not exceed the density
+        // the thread has been admitted with, otherwise start a new period
+        if now >= params.abs_deadline
+            || params.budget * params.deadline > (params.abs_deadline - now) * params.runtime
+        {
+            params.start_period(now);
+        }
+        let abs_deadline = params.abs_deadline;
+        drop(task_inner);
+        self.enqueue(task, abs_deadline);
+    }
+    /// Take the thread with the earliest deadline
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.replenish();
+        let key = *self.ready_queue.keys().next()?;
+        self.ready_queue.remove(&key)
+    }
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) {
+        if let Some(key) = self
+            .ready_queue
+            .iter()
+            .find(|(_, t)| Arc::ptr_eq(t, task))
+            .map(|(key, _)| *key)
+        {
+            self.ready_queue.remove(&key);
+        }
+        self.throttled.retain(|t| !Arc::ptr_eq(t, task));
+    }
+    /// Enforce the budget of the running thread, and preempt it in favor of
+    /// a thread with an earlier deadline
+    fn on_tick(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.sched.account_runtime();
+        let params = task_inner.sched.rt.as_ref().unwrap();
+        if params.budget == 0 {
+            return true;
+        }
+        let abs_deadline = params.abs_deadline;
+        drop(task_inner);
+        match self.ready_queue.keys().next() {
+            Some((earliest, _)) => *earliest < abs_deadline,
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Multi-level feedback queue
+
+use super::Scheduler;
+use crate::task::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// Time slice of each level in timer ticks, from the highest priority level down
+const QUANTA: [usize; 3] = [1, 2, 4];
+const LEVELS: usize = QUANTA.len();
+/// Every thread goes back to the highest level this often, in timer ticks
+const BOOST_INTERVAL: usize = 100;
+
+/// MLFQ scheduler
+///
+/// A thread that uses up its time slice moves one level down, so CPU hogs
+/// sink while interactive threads, which block before their slice is over,
+/// stay on top. Running threads are preempted as soon as a higher level has
+/// something ready, and a periodic boost keeps the lowest levels from
+/// starving.
+pub struct MlfqScheduler {
+    queues: [VecDeque<Arc<TaskControlBlock>>; LEVELS],
+    /// Timer ticks since boot
+    ticks: usize,
+    /// Number of boosts so far. Threads whose epoch is older were away
+    /// (running or blocked) during a boost and are boosted when they return.
+    epoch: usize,
+}
+
+impl MlfqScheduler {
+    fn boost(&mut self) {
+        self.epoch += 1;
+        for level in 1..LEVELS {
+            while let Some(task) = self.queues[level].pop_front() {
+                self.queues[0].push_back(task);
+            }
+        }
+        for task in self.queues[0].iter() {
+            let mut task_inner = task.inner_exclusive_access();
+            task_inner.sched.level = 0;
+            task_inner.sched.ticks = 0;
+            task_inner.sched.epoch = self.epoch;
+        }
+    }
+}
+
+impl Scheduler for MlfqScheduler {
+    fn new() -> Self {
+        Self {
+            queues: [VecDeque::new(), VecDeque::new(), VecDeque::new()],
+            ticks: 0,
+            epoch: 0,
+        }
+    }
+    /// Queue the thread at its level, or at the top if a boost happened
+    /// since it was last queued
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let mut task_inner = task.inner_exclusive_access();
+        if task_inner.sched.epoch != self.epoch {
+            task_inner.sched.level = 0;
+            task_inner.sched.ticks = 0;
+            task_inner.sched.epoch = self.epoch;
+        }
+        let level = task_inner.sched.level;
+        drop(task_inner);
+        self.queues[level].push_back(task);
+    }
+    /// Take the first thread of the highest non-empty level
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.queues.iter_mut().find_map(|queue| queue.pop_front())
+    }
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) {
+        for queue in self.queues.iter_mut() {
+            queue.retain(|t| !Arc::ptr_eq(t, task));
+        }
+    }
+    fn on_tick(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        self.ticks += 1;
+        if self.ticks % BOOST_INTERVAL == 0 {
+            self.boost();
+        }
+        let mut task_inner = task.inner_exclusive_access();
+        let sched = &mut task_inner.sched;
+        if sched.epoch != self.epoch {
+            sched.level = 0;
+            sched.ticks = 0;
+            sched.epoch = self.epoch;
+        }
+        sched.ticks += 1;
+        if sched.ticks >= QUANTA[sched.level] {
+            // used up its slice
+            sched.level = (sched.level + 1).min(LEVELS - 1);
+            sched.ticks = 0;
+            return true;
+        }
+        let level = sched.level;
+        drop(task_inner);
+        self.queues[..level].iter().any(|queue| !queue.is_empty())
+    }
+    // Blocking keeps both the level and the ticks used at that level, so a
+    // thread cannot stay on top by blocking right before its slice is over.
+}
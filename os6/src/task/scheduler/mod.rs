@@ -0,0 +1,103 @@
+//! Scheduling policies
+//!
+//! Every policy implements [`Scheduler`]. Which one backs the ready queue is
+//! decided at build time through cargo features (`sched-mlfq`,
+//! `sched-cfs`), stride scheduling being the default. Real-time threads
+//! are handled by [`EdfScheduler`] whatever the policy.
+
+mod cfs;
+mod edf;
+mod mlfq;
+mod stride;
+
+use super::TaskControlBlock;
+use crate::config::{BIG_STRIDE, DEFAULT_PRIORITY};
+use crate::timer::get_time_us;
+use alloc::sync::Arc;
+
+pub use cfs::CfsScheduler;
+pub use edf::{EdfScheduler, RtParams};
+pub use mlfq::MlfqScheduler;
+pub use stride::StrideScheduler;
+
+#[cfg(all(feature = "sched-mlfq", feature = "sched-cfs"))]
+compile_error!("only one scheduling policy can be enabled");
+
+#[cfg(feature = "sched-mlfq")]
+pub type SelectedScheduler = MlfqScheduler;
+#[cfg(feature = "sched-cfs")]
+pub type SelectedScheduler = CfsScheduler;
+#[cfg(not(any(feature = "sched-mlfq", feature = "sched-cfs")))]
+pub type SelectedScheduler = StrideScheduler;
+
+/// Interface between the ready queue and the rest of the kernel
+pub trait Scheduler {
+    fn new() -> Self
+    where
+        Self: Sized;
+    /// A thread becomes ready to run
+    fn add(&mut self, task: Arc<TaskControlBlock>);
+    /// Pick the next thread to run and take it out of the ready queue
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>>;
+    /// Take a thread out of the ready queue if it is there
+    fn remove(&mut self, task: &Arc<TaskControlBlock>);
+    /// A timer tick elapsed while `task` was running.
+    /// Return whether it should give up the CPU.
+    fn on_tick(&mut self, task: &Arc<TaskControlBlock>) -> bool;
+    /// `task` is about to block before its time slice is over
+    fn on_block(&mut self, _task: &Arc<TaskControlBlock>) {}
+}
+
+/// Per-thread state used by the scheduling policies
+pub struct SchedInfo {
+    /// Stride scheduling priority, at least 2
+    pub priority: usize,
+    /// Stride scheduling pass, wrapping around on overflow
+    pub pass: usize,
+    /// MLFQ level, 0 being the highest priority
+    pub level: usize,
+    /// MLFQ ticks consumed at the current level
+    pub ticks: usize,
+    /// MLFQ boost period the level belongs to
+    pub epoch: usize,
+    /// CFS virtual runtime in microseconds, weighted by the priority
+    pub vruntime: usize,
+    /// When the thread was last switched to, or last accounted, in microseconds
+    pub exec_start_us: usize,
+    /// Parameters of real-time threads, None for normal ones
+    pub rt: Option<RtParams>,
+}
+
+impl SchedInfo {
+    pub fn new() -> Self {
+        Self {
+            priority: DEFAULT_PRIORITY,
+            pass: 0,
+            level: 0,
+            ticks: 0,
+            epoch: 0,
+            vruntime: 0,
+            exec_start_us: 0,
+            rt: None,
+        }
+    }
+    /// How far the pass moves each time the thread is scheduled
+    pub fn stride(&self) -> usize {
+        // a huge priority must not stop the pass from moving at all
+        (BIG_STRIDE / self.priority).max(1)
+    }
+    /// Charge the CPU time used since `exec_start_us` to the vruntime,
+    /// and to the real-time budget if any.
+    ///
+    /// The priority acts as the weight: a thread with twice the default
+    /// priority sees its vruntime grow half as fast.
+    pub fn account_runtime(&mut self) {
+        let now = get_time_us();
+        let delta = now - self.exec_start_us;
+        self.vruntime += delta * DEFAULT_PRIORITY / self.priority;
+        if let Some(rt) = self.rt.as_mut() {
+            rt.budget = rt.budget.saturating_sub(delta);
+        }
+        self.exec_start_us = now;
+    }
+}
@@ -0,0 +1,90 @@
+//! Stride scheduling
+
+use super::Scheduler;
+use crate::config::BIG_STRIDE;
+use crate::task::TaskControlBlock;
+use alloc::collections::BinaryHeap;
+use alloc::sync::Arc;
+use core::cmp::Ordering;
+
+/// A ready thread along with its pass when it was enqueued
+struct StrideEntry {
+    pass: usize,
+    task: Arc<TaskControlBlock>,
+}
+
+impl PartialEq for StrideEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.pass == other.pass
+    }
+}
+impl Eq for StrideEntry {}
+impl PartialOrd for StrideEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for StrideEntry {
+    /// Reversed so that the BinaryHeap pops the smallest pass first.
+    ///
+    /// Passes wrap around, so they are compared through their signed
+    /// difference, which is right as long as all of them are less than
+    /// half the range apart. The manager makes sure they stay within
+    /// BIG_STRIDE of each other.
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.pass.wrapping_sub(self.pass) as isize).cmp(&0)
+    }
+}
+
+/// Stride scheduler: the ready thread with the smallest pass runs next,
+/// and the pass grows by a stride inversely proportional to the priority
+pub struct StrideScheduler {
+    ready_queue: BinaryHeap<StrideEntry>,
+    /// Pass of the thread fetched last, which no ready thread is behind
+    min_pass: usize,
+}
+
+impl Scheduler for StrideScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: BinaryHeap::new(),
+            min_pass: 0,
+        }
+    }
+    /// Add thread back to ready queue
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let mut task_inner = task.inner_exclusive_access();
+        // a new thread, or one that has been blocked for long, may be far
+        // behind the others; let it start over from the current minimum
+        // instead of monopolizing the CPU or breaking the comparison
+        if task_inner.sched.pass.wrapping_sub(self.min_pass) > BIG_STRIDE {
+            task_inner.sched.pass = self.min_pass;
+        }
+        let pass = task_inner.sched.pass;
+        drop(task_inner);
+        self.ready_queue.push(StrideEntry { pass, task });
+    }
+    /// Take the thread with the smallest pass out of the ready queue
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let StrideEntry { pass, task } = self.ready_queue.pop()?;
+        self.min_pass = pass;
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.sched.pass = pass.wrapping_add(task_inner.sched.stride());
+        drop(task_inner);
+        Some(task)
+    }
+    /// Remove a thread from the ready queue if it is there
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) {
+        let remaining: BinaryHeap<StrideEntry> = self
+            .ready_queue
+            .drain()
+            .filter(|entry| !Arc::ptr_eq(&entry.task, task))
+            .collect();
+        self.ready_queue = remaining;
+    }
+    /// Every tick is a scheduling point
+    fn on_tick(&mut self, _task: &Arc<TaskControlBlock>) -> bool {
+        true
+    }
+}
@@ -0,0 +1,325 @@
+//! Signals of a process
+//!
+//! Pending and blocked signals, as well as the handlers, belong to the
+//! process. They are delivered to whichever of its threads returns to user
+//! space first: a user handler runs on that thread, whose trap context is
+//! saved until the handler calls sigreturn.
+
+use super::manager::pgid2processes;
+use super::process::ProcessControlBlockInner;
+use super::{
+    block_current_and_run_next, current_process, current_task, exit_process_and_run_next,
+    interrupt_task, ProcessControlBlock, INITPROC,
+};
+use alloc::sync::Arc;
+
+/// Largest signal number
+pub const MAX_SIG: usize = 31;
+/// Handler taking the default action of a signal
+pub const SIG_DFL: usize = 0;
+/// Handler ignoring a signal
+pub const SIG_IGN: usize = 1;
+
+bitflags! {
+    /// A set of signals, bit i for signal number i
+    pub struct SignalFlags: u32 {
+        const SIGHUP    = 1 << 1;
+        const SIGINT    = 1 << 2;
+        const SIGQUIT   = 1 << 3;
+        const SIGILL    = 1 << 4;
+        const SIGTRAP   = 1 << 5;
+        const SIGABRT   = 1 << 6;
+        const SIGBUS    = 1 << 7;
+        const SIGFPE    = 1 << 8;
+        const SIGKILL   = 1 << 9;
+        const SIGUSR1   = 1 << 10;
+        const SIGSEGV   = 1 << 11;
+        const SIGUSR2   = 1 << 12;
+        const SIGPIPE   = 1 << 13;
+        const SIGALRM   = 1 << 14;
+        const SIGTERM   = 1 << 15;
+        const SIGSTKFLT = 1 << 16;
+        const SIGCHLD   = 1 << 17;
+        const SIGCONT   = 1 << 18;
+        const SIGSTOP   = 1 << 19;
+        const SIGTSTP   = 1 << 20;
+        const SIGTTIN   = 1 << 21;
+        const SIGTTOU   = 1 << 22;
+        const SIGURG    = 1 << 23;
+        const SIGXCPU   = 1 << 24;
+        const SIGXFSZ   = 1 << 25;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF   = 1 << 27;
+        const SIGWINCH  = 1 << 28;
+        const SIGIO     = 1 << 29;
+        const SIGPWR    = 1 << 30;
+        const SIGSYS    = 1 << 31;
+    }
+}
+
+/// What a signal does when its handler is [`SIG_DFL`]
+pub enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+impl SignalFlags {
+    /// The set holding signal `signum` only, if it is a valid one
+    pub fn from_signum(signum: usize) -> Option<Self> {
+        if signum == 0 || signum > MAX_SIG {
+            return None;
+        }
+        Self::from_bits(1 << signum)
+    }
+    /// Number of the lowest signal in the set
+    pub fn lowest_signum(&self) -> Option<usize> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.bits.trailing_zeros() as usize)
+        }
+    }
+    /// Signals whose handlers cannot be changed and which cannot be blocked
+    pub fn unmaskable() -> Self {
+        Self::SIGKILL | Self::SIGSTOP
+    }
+    /// Signals stopping the process by default
+    pub fn stop_signals() -> Self {
+        Self::SIGSTOP | Self::SIGTSTP | Self::SIGTTIN | Self::SIGTTOU
+    }
+    /// Default action of a set holding a single signal
+    pub fn default_action(&self) -> DefaultAction {
+        if Self::stop_signals().contains(*self) {
+            DefaultAction::Stop
+        } else if *self == Self::SIGCONT {
+            DefaultAction::Continue
+        } else if (Self::SIGCHLD | Self::SIGURG | Self::SIGWINCH).contains(*self) {
+            DefaultAction::Ignore
+        } else {
+            DefaultAction::Terminate
+        }
+    }
+}
+
+/// How a signal is handled, as set by sigaction
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SignalAction {
+    /// [`SIG_DFL`], [`SIG_IGN`] or the address of a user handler
+    pub handler: usize,
+    /// Signals blocked in addition while the handler runs
+    pub mask: SignalFlags,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalFlags::empty(),
+        }
+    }
+}
+
+/// Handlers of all signals of a process, indexed by signal number
+#[derive(Clone)]
+pub struct SignalActions {
+    pub table: [SignalAction; MAX_SIG + 1],
+}
+
+impl SignalActions {
+    pub fn new() -> Self {
+        Self {
+            table: [SignalAction::default(); MAX_SIG + 1],
+        }
+    }
+    /// Reset user handlers to the default action, as exec does; ignored
+    /// signals stay ignored
+    pub fn reset_handlers(&mut self) {
+        for action in self.table.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SignalAction::default();
+            }
+        }
+    }
+}
+
+/// Send `signal` to `process`
+///
+/// SIGCONT and SIGKILL resume a stopped process right away, so that the
+/// latter can be delivered at all. If the process has to act upon the
+/// signal, its threads waiting interruptibly in the kernel are woken up to
+/// get back to user space, where it is delivered.
+pub fn send_signal(process: &Arc<ProcessControlBlock>, signal: SignalFlags) {
+    let mut process_inner = process.inner_exclusive_access();
+    if signal == SignalFlags::SIGCONT {
+        process_inner.signals.remove(SignalFlags::stop_signals());
+    } else if SignalFlags::stop_signals().contains(signal) {
+        process_inner.signals.remove(SignalFlags::SIGCONT);
+    }
+    if signal == SignalFlags::SIGCONT || signal == SignalFlags::SIGKILL {
+        process_inner.stopped = false;
+        process_inner.stop_report = None;
+        process_inner.stop_queue.wake_all();
+    }
+    process_inner.signals |= signal;
+    if !process_inner.is_zombie() && signal_pending(&process_inner) {
+        for task in process_inner.tasks.iter().flatten() {
+            interrupt_task(task);
+        }
+    }
+}
+
+/// Send `signal` to every process in group `pgid`, return false if there is
+/// none
+///
+/// initproc is left out: the console belongs to its group until a shell
+/// takes it over, and ^C must not bring the whole system down.
+pub fn send_group_signal(pgid: usize, signal: SignalFlags) -> bool {
+    let processes = pgid2processes(pgid);
+    for process in processes.iter() {
+        if !Arc::ptr_eq(process, &INITPROC) {
+            send_signal(process, signal);
+        }
+    }
+    !processes.is_empty()
+}
+
+/// Whether the current process is stopped or has a signal to act upon, in
+/// which case a thread blocking in the kernel should get back to user space
+pub fn current_signal_pending() -> bool {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    signal_pending(&process_inner)
+}
+
+/// Whether the process is stopped or has a signal to act upon
+fn signal_pending(process_inner: &ProcessControlBlockInner) -> bool {
+    if process_inner.stopped {
+        return true;
+    }
+    let mut pending =
+        process_inner.signals - (process_inner.signal_mask - SignalFlags::unmaskable());
+    while let Some(signum) = pending.lowest_signum() {
+        let signal = SignalFlags::from_signum(signum).unwrap();
+        pending.remove(signal);
+        let acts = match process_inner.signal_actions.table[signum].handler {
+            SIG_IGN => false,
+            SIG_DFL => matches!(
+                signal.default_action(),
+                DefaultAction::Terminate | DefaultAction::Stop
+            ),
+            _ => true,
+        };
+        if acts {
+            return true;
+        }
+    }
+    false
+}
+
+/// Raise `signal` for a fault of the current thread, which cannot go on if
+/// the signal is blocked, ignored, or raised inside a handler: the default
+/// action is taken then.
+pub fn send_fault_signal(signal: SignalFlags) {
+    let task = current_task().unwrap();
+    let handling = task.inner_exclusive_access().handling_sig.is_some();
+    let process = task.process.upgrade().unwrap();
+    let mut process_inner = process.inner_exclusive_access();
+    let signum = signal.lowest_signum().unwrap();
+    if handling
+        || process_inner.signal_mask.contains(signal)
+        || process_inner.signal_actions.table[signum].handler == SIG_IGN
+    {
+        process_inner.signal_actions.table[signum] = SignalAction::default();
+        process_inner.signal_mask.remove(signal);
+    }
+    process_inner.signals |= signal;
+}
+
+/// Deliver the pending signals of the current process to the current thread
+/// on its way back to user space
+///
+/// Signals with a user handler wait while the thread is running another
+/// handler. The thread blocks here while the process is stopped; the thread
+/// stopping it wakes up the parent, which may be waiting with WUNTRACED.
+pub fn handle_signals() {
+    loop {
+        let task = current_task().unwrap();
+        let process = task.process.upgrade().unwrap();
+        let mut process_inner = process.inner_exclusive_access();
+        if process_inner.is_zombie() {
+            // about to exit in trap_return
+            return;
+        }
+        if process_inner.stopped && !process_inner.signals.contains(SignalFlags::SIGKILL) {
+            // woken up by SIGCONT or SIGKILL
+            process_inner.stop_queue.add_current();
+            drop(process_inner);
+            drop(process);
+            drop(task);
+            block_current_and_run_next();
+            continue;
+        }
+        let mut task_inner = task.inner_exclusive_access();
+        let mut blocked = process_inner.signal_mask;
+        if let Some(handling_sig) = task_inner.handling_sig {
+            blocked |= process_inner.signal_actions.table[handling_sig].mask;
+        }
+        blocked.remove(SignalFlags::unmaskable());
+        let mut deliverable = process_inner.signals - blocked;
+        let mut stopped_now = false;
+        while let Some(signum) = deliverable.lowest_signum() {
+            let signal = SignalFlags::from_signum(signum).unwrap();
+            deliverable.remove(signal);
+            let action = process_inner.signal_actions.table[signum];
+            if action.handler == SIG_IGN {
+                process_inner.signals.remove(signal);
+                continue;
+            }
+            if action.handler != SIG_DFL {
+                if task_inner.handling_sig.is_some() {
+                    continue;
+                }
+                // run the handler with the signal number in a0, and come
+                // back to the current trap context through sigreturn
+                process_inner.signals.remove(signal);
+                task_inner.handling_sig = Some(signum);
+                let trap_cx = task_inner.get_trap_cx();
+                task_inner.trap_cx_backup = Some(*trap_cx);
+                trap_cx.sepc = action.handler;
+                trap_cx.x[10] = signum;
+                continue;
+            }
+            process_inner.signals.remove(signal);
+            match signal.default_action() {
+                DefaultAction::Terminate => {
+                    drop(task_inner);
+                    drop(process_inner);
+                    drop(process);
+                    drop(task);
+                    exit_process_and_run_next(-(signum as i32));
+                    unreachable!();
+                }
+                DefaultAction::Stop => {
+                    process_inner.stopped = true;
+                    process_inner.stop_report = Some(signum);
+                    stopped_now = true;
+                }
+                DefaultAction::Ignore | DefaultAction::Continue => {}
+            }
+        }
+        if !process_inner.stopped {
+            return;
+        }
+        if stopped_now {
+            let parent = process_inner.parent.as_ref().and_then(|parent| parent.upgrade());
+            drop(task_inner);
+            drop(process_inner);
+            if let Some(parent) = parent {
+                parent.inner_exclusive_access().child_exit.wake_all();
+            }
+        }
+    }
+}
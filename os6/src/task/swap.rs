@@ -0,0 +1,93 @@
+//! Reclaiming frames by swapping user pages out
+//!
+//! Frames are reclaimed before they run out, whenever a thread enters the
+//! kernel for a syscall or a page fault and holds no lock yet: what it
+//! allocates afterwards, e.g. the page tables of fork, comes out of the
+//! frames kept free. Should they run out all the same, a page fault reclaims
+//! frames once more before giving up. The clock hand goes round the resident
+//! pages of all processes, in the order of pid and then of vpn.
+//!
+//! A page must not go while some TLB may still map it, or while the kernel
+//! may be accessing its frame on behalf of a syscall. Harts flush their TLB
+//! both on entering the kernel and on going back to user space, so a
+//! process is only considered if none of its threads is on another hart,
+//! and the current one, if among them, is not inside a syscall. Threads
+//! blocked in a syscall do not count: the frames of the user buffers they
+//! hold are referenced by [`UserBuffer`](crate::mm::UserBuffer), and the
+//! clock skips shared frames. [`run_tasks`](super::run_tasks) puts a thread
+//! on a hart with its PCB locked, which keeps it that way while we hold it.
+
+use super::manager::PID2PCB;
+use super::process::ProcessControlBlockInner;
+use super::{current_task, TaskControlBlock, TaskStatus};
+use crate::config::{SWAP_HIGH_WATERMARK, SWAP_LOW_WATERMARK};
+use crate::mm::{free_frame_count, VirtPageNum};
+use crate::sync::SpinNoIrqLock;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+lazy_static! {
+    /// Where the clock hand is: a pid, and a page in the address space of
+    /// that process
+    static ref CLOCK_HAND: SpinNoIrqLock<(usize, VirtPageNum)> =
+        SpinNoIrqLock::new((0, VirtPageNum(0)));
+}
+
+/// Swap user pages out if free frames run low, until there are enough again
+///
+/// The caller must not hold any lock, as the PCBs are locked one by one.
+pub fn reclaim_frames() {
+    if free_frame_count() >= SWAP_LOW_WATERMARK {
+        return;
+    }
+    // one hart at a time
+    let mut hand = CLOCK_HAND.lock();
+    let current = current_task().unwrap();
+    let processes: Vec<_> = PID2PCB
+        .lock()
+        .iter()
+        .map(|(pid, process)| (*pid, Arc::clone(process)))
+        .collect();
+    // the hand goes round twice giving referenced pages another chance and
+    // taking clean pages only, then twice more writing dirty ones out
+    for round in 0..4 {
+        let start = processes
+            .iter()
+            .position(|(pid, _)| *pid >= hand.0)
+            .unwrap_or(0);
+        for i in 0..processes.len() {
+            let wanted = SWAP_HIGH_WATERMARK.saturating_sub(free_frame_count());
+            if wanted == 0 {
+                return;
+            }
+            let (pid, process) = &processes[(start + i) % processes.len()];
+            let from = if *pid == hand.0 {
+                hand.1
+            } else {
+                VirtPageNum(0)
+            };
+            let mut process_inner = process.inner_exclusive_access();
+            let next = if may_swap_out(&process_inner, &current) {
+                process_inner.memory_set.swap_out(from, wanted, round >= 2)
+            } else {
+                None
+            };
+            *hand = match next {
+                Some(vpn) => (*pid, vpn),
+                None => (*pid + 1, VirtPageNum(0)),
+            };
+        }
+    }
+}
+
+/// Whether pages of the process may be swapped out, see the module doc
+fn may_swap_out(process_inner: &ProcessControlBlockInner, current: &Arc<TaskControlBlock>) -> bool {
+    !process_inner.is_zombie()
+        && process_inner.tasks.iter().flatten().all(|task| {
+            let task_inner = task.inner_exclusive_access();
+            task_inner.task_status == TaskStatus::Zombie
+                || !task_inner.on_cpu
+                || !task_inner.in_syscall && Arc::ptr_eq(task, current)
+        })
+}
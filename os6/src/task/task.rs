@@ -1,288 +1,142 @@
-//! Types related to task management & Functions for completely changing TCB
+//! Types related to threads, the unit of scheduling
 
-use super::TaskContext;
-use super::{pid_alloc, KernelStack, PidHandle};
-use crate::config::TRAP_CONTEXT;
-use crate::mm::{MemorySet, PhysPageNum, VirtAddr, KERNEL_SPACE};
-use crate::sync::UPSafeCell;
-use crate::trap::{trap_handler, TrapContext};
+use super::id::TaskUserRes;
+use super::{kstack_alloc, KernelStack, ProcessControlBlock, TaskContext};
+use super::scheduler::SchedInfo;
+use crate::config::MAX_SYSCALL_NUM;
+use crate::mm::PhysPageNum;
+use crate::sync::{SpinNoIrqLock, SpinNoIrqLockGuard};
+use crate::trap::TrapContext;
 use alloc::sync::{Arc, Weak};
-use alloc::vec::Vec;
-use core::cell::RefMut;
-use crate::fs::{File, Stdin, Stdout};
-use alloc::string::String;
-use crate::mm::translated_refmut;
 
 /// Task control block structure
 ///
-/// Directly save the contents that will not change during running
+/// Each thread of a process owns one. Directly save the contents that will
+/// not change during running
 pub struct TaskControlBlock {
     // immutable
-    /// Process identifier
-    pub pid: PidHandle,
-    /// Kernel stack corresponding to PID
-    pub kernel_stack: KernelStack,
+    /// The process this thread belongs to
+    pub process: Weak<ProcessControlBlock>,
+    /// Kernel stack of this thread
+    pub kstack: KernelStack,
     // mutable
-    inner: UPSafeCell<TaskControlBlockInner>,
+    inner: SpinNoIrqLock<TaskControlBlockInner>,
 }
 
-/// Structure containing more process content
+/// Structure containing more thread content
 ///
 /// Store the contents that will change during operation
-/// and are wrapped by UPSafeCell to provide mutual exclusion
+/// and are wrapped by SpinNoIrqLock to provide mutual exclusion
 pub struct TaskControlBlockInner {
+    /// TID, user stack and trap context of this thread.
+    /// It is taken away as soon as the thread exits.
+    pub res: Option<TaskUserRes>,
     /// The physical page number of the frame where the trap context is placed
     pub trap_cx_ppn: PhysPageNum,
-    /// Application data can only appear in areas
-    /// where the application address space is lower than base_size
-    pub base_size: usize,
     /// Save task context
     pub task_cx: TaskContext,
-    /// Maintain the execution status of the current process
+    /// Maintain the execution status of the current thread
     pub task_status: TaskStatus,
-    /// Application address space
-    pub memory_set: MemorySet,
-    /// Parent process of the current process.
-    /// Weak will not affect the reference count of the parent
-    pub parent: Option<Weak<TaskControlBlock>>,
-    /// A vector containing TCBs of all child processes of the current process
-    pub children: Vec<Arc<TaskControlBlock>>,
-    /// It is set when active exit or execution error occurs
-    pub exit_code: i32,
-    pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    /// It is set when the thread exits
+    pub exit_code: Option<i32>,
+    /// Number of times each syscall has been made by this thread
+    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    /// When the thread was scheduled for the first time, in milliseconds
+    pub first_run_time: Option<usize>,
+    /// Bookkeeping of the scheduling policy
+    pub sched: SchedInfo,
+    /// Whether some hart is still running on the kernel stack of this thread.
+    /// It is cleared only after the thread has switched back to the idle
+    /// control flow, so no other hart may switch to it before that.
+    pub on_cpu: bool,
+    /// Whether the thread is inside a syscall, which may access frames of
+    /// user pages while it runs, so that they must not be swapped out then.
+    /// A syscall blocking with a user buffer keeps its frames referenced.
+    pub in_syscall: bool,
+    /// Signal whose user handler the thread is running
+    pub handling_sig: Option<usize>,
+    /// Trap context to go back to when the signal handler returns
+    pub trap_cx_backup: Option<TrapContext>,
+    /// What may wake the thread up while it waits for an event
+    pub wait_state: WaitState,
 }
 
 /// Simple access to its internal fields
 impl TaskControlBlockInner {
-    /*
-    pub fn get_task_cx_ptr2(&self) -> *const usize {
-        &self.task_cx_ptr as *const usize
-    }
-    */
     pub fn get_trap_cx(&self) -> &'static mut TrapContext {
         self.trap_cx_ppn.get_mut()
     }
-    pub fn get_user_token(&self) -> usize {
-        self.memory_set.token()
-    }
-    fn get_status(&self) -> TaskStatus {
-        self.task_status
-    }
-    pub fn is_zombie(&self) -> bool {
-        self.get_status() == TaskStatus::Zombie
-    }
-    pub fn alloc_fd(&mut self) -> usize {
-        if let Some(fd) = (0..self.fd_table.len())
-            .find(|fd| self.fd_table[*fd].is_none()) {
-            fd
-        } else {
-            self.fd_table.push(None);
-            self.fd_table.len() - 1
-        }
-    }
 }
 
 impl TaskControlBlock {
-    /// Get the mutex to get the RefMut TaskControlBlockInner
-    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
-        self.inner.exclusive_access()
-    }
-
-    /// Create a new process
-    ///
-    /// At present, it is only used for the creation of initproc
-    pub fn new(elf_data: &[u8]) -> Self {
-        // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
-        // alloc a pid and a kernel stack in kernel space
-        let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
-        let kernel_stack_top = kernel_stack.get_top();
-        // push a task context which goes to trap_return to the top of kernel stack
-        let task_control_block = Self {
-            pid: pid_handle,
-            kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: user_sp,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: None,
-                    children: Vec::new(),
-                    exit_code: 0,
-                    fd_table: alloc::vec![
-                        // 0 -> stdin
-                        Some(Arc::new(Stdin)),
-                        // 1 -> stdout
-                        Some(Arc::new(Stdout)),
-                        // 2 -> stderr
-                        Some(Arc::new(Stdout)),
-                    ],
-                })
-            },
-        };
-        // prepare TrapContext in user space
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-        *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            kernel_stack_top,
-            trap_handler as usize,
-        );
-        task_control_block
+    /// Lock TaskControlBlockInner of this block
+    pub fn inner_exclusive_access(&self) -> SpinNoIrqLockGuard<'_, TaskControlBlockInner> {
+        self.inner.lock()
     }
-    /// Load a new elf to replace the original application address space and start execution
-    pub fn exec(&self, elf_data: &[u8]) {
-        // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, mut user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
-        // **** access inner exclusively
-        let mut inner = self.inner_exclusive_access();
-        // substitute memory_set
-        inner.memory_set = memory_set;
-        // update trap_cx ppn
-        inner.trap_cx_ppn = trap_cx_ppn;
-        // initialize trap_cx
-        let trap_cx = inner.get_trap_cx();
-        *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            self.kernel_stack.get_top(),
-            trap_handler as usize,
-        );
-        // **** release inner automatically
+    /// Get token of the address space shared by the threads of the process
+    pub fn get_user_token(&self) -> usize {
+        let process = self.process.upgrade().unwrap();
+        let inner = process.inner_exclusive_access();
+        inner.memory_set.token()
     }
-    /// Fork from parent to child
-    pub fn fork(self: &Arc<TaskControlBlock>) -> Arc<TaskControlBlock> {
-        // ---- access parent PCB exclusively
-        let mut parent_inner = self.inner_exclusive_access();
-        // copy user space(include trap context)
-        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
-        let trap_cx_ppn = memory_set
-            .translate(VirtAddr::from(TRAP_CONTEXT).into())
-            .unwrap()
-            .ppn();
-        // alloc a pid and a kernel stack in kernel space
-        let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
-        let kernel_stack_top = kernel_stack.get_top();
-        let mut new_fd_table: Vec<Option<Arc<dyn File + Send + Sync>>> = Vec::new();
-        // clone all fds from parent to child
-        for fd in parent_inner.fd_table.iter() {
-            if let Some(file) = fd {
-                new_fd_table.push(Some(file.clone()));
-            } else {
-                new_fd_table.push(None);
-            }
+    /// Create a new thread in `process`
+    ///
+    /// When `alloc_user_res` is false the user stack and trap context are
+    /// assumed to be present already, e.g. copied by fork.
+    pub fn new(
+        process: Arc<ProcessControlBlock>,
+        ustack_base: usize,
+        alloc_user_res: bool,
+    ) -> Self {
+        let res = TaskUserRes::new(Arc::clone(&process), ustack_base, alloc_user_res);
+        let trap_cx_ppn = res.trap_cx_ppn();
+        let kstack = kstack_alloc();
+        let kstack_top = kstack.get_top();
+        Self {
+            process: Arc::downgrade(&process),
+            kstack,
+            inner: SpinNoIrqLock::new(TaskControlBlockInner {
+                res: Some(res),
+                trap_cx_ppn,
+                task_cx: TaskContext::goto_trap_return(kstack_top),
+                task_status: TaskStatus::Ready,
+                exit_code: None,
+                syscall_times: [0; MAX_SYSCALL_NUM],
+                first_run_time: None,
+                sched: SchedInfo::new(),
+                on_cpu: false,
+                in_syscall: false,
+                handling_sig: None,
+                trap_cx_backup: None,
+                wait_state: WaitState::None,
+            }),
         }
-        let task_control_block = Arc::new(TaskControlBlock {
-            pid: pid_handle,
-            kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: parent_inner.base_size,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: Some(Arc::downgrade(self)),
-                    children: Vec::new(),
-                    exit_code: 0,
-                    fd_table: new_fd_table,
-                })
-            },
-        });
-        // add child
-        parent_inner.children.push(task_control_block.clone());
-        // modify kernel_sp in trap_cx
-        // **** access children PCB exclusively
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-        trap_cx.kernel_sp = kernel_stack_top;
-        // return
-        task_control_block
-        // ---- release parent PCB automatically
-        // **** release children PCB automatically
     }
-    pub fn getpid(&self) -> usize {
-        self.pid.0
-    }
-
-    pub fn create_new_task_from_elf(self: &Arc<TaskControlBlock>, elf_data: &[u8]) -> Arc<TaskControlBlock> {
-        // collect information from elf spce
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
-        // I don't know what this is doing, i just copy it
-        // basically we just putting a fixed position as a return place of trap ? [change to ppn]
-        let trap_cx_ppn = memory_set
-                                    .translate(VirtAddr::from(TRAP_CONTEXT).into())
-                                    .unwrap()
-                                    .ppn();
-        // alloc pid and alloc kernel_stack in kernel space
-        let pid = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid);
-        let kernel_stack_top = kernel_stack.get_top();
-    
-        
-        let task_control_block = Arc::new(TaskControlBlock {
-            pid,
-            kernel_stack,
-            inner: unsafe {
-                UPSafeCell::new(TaskControlBlockInner {
-                    trap_cx_ppn,
-                    base_size: user_sp,
-                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
-                    task_status: TaskStatus::Ready,
-                    memory_set,
-                    parent: Some(Arc::downgrade(self)),
-                    children: Vec::new(),
-                    exit_code: 0,
-                    fd_table: alloc::vec![
-                        Some(Arc::new(Stdin)),
-                        Some(Arc::new(Stdout)),
-                        Some(Arc::new(Stdout)),
-                    ]
-                    // piro: 16,
-                    // pass:0,
-                })
-            },
-        });
-
-        // adding this process as his parent's children [ we are in his parent right now ]
-        let mut parent_inner = self.inner.exclusive_access();
-        parent_inner.children.push(task_control_block.clone());
-        // parent_inner.children.push(task_control_block);
-
-        // init trap context
-        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
-        *trap_cx = TrapContext::app_init_context(
-            entry_point,
-            user_sp,
-            KERNEL_SPACE.exclusive_access().token(),
-            kernel_stack_top,
-            trap_handler as usize,
-        );
-
-        task_control_block
-    }
-
-
 }
 
 #[derive(Copy, Clone, PartialEq)]
-/// task status: UnInit, Ready, Running, Exited
+/// task status: UnInit, Ready, Running, Zombie, Blocked
+///
+/// The order of the variants is part of the ABI of sys_task_info.
 pub enum TaskStatus {
     UnInit,
     Ready,
     Running,
     Zombie,
+    /// Waiting in some wait queue, never picked by the scheduler
+    Blocked,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+/// Whether a thread waits for an event, see
+/// [`block_current_and_run_next`](super::block_current_and_run_next)
+pub enum WaitState {
+    /// Not waiting, or woken up by the event it waited for
+    None,
+    /// Only the event wakes the thread up
+    Uninterruptible,
+    /// A signal to act upon wakes the thread up as well
+    Interruptible,
+    /// Woken up by a signal before the event came
+    Interrupted,
 }
@@ -1,10 +1,21 @@
 //! RISC-V timer-related functionality
+//!
+//! Besides reading the clock and arming the next timer interrupt, it keeps
+//! track of the threads sleeping until some deadline.
+
+use core::cmp::Ordering;
 
 use crate::config::CLOCK_FREQ;
 use crate::sbi::set_timer;
+use crate::sync::SpinNoIrqLock;
+use crate::task::{wakeup_task, TaskControlBlock};
+use alloc::collections::BinaryHeap;
+use alloc::sync::Arc;
+use lazy_static::*;
 use riscv::register::time;
 
 const TICKS_PER_SEC: usize = 100;
+const MSEC_PER_SEC: usize = 1000;
 const MICRO_PER_SEC: usize = 1_000_000;
 
 /// read the `mtime` register
@@ -12,6 +23,11 @@ pub fn get_time() -> usize {
     time::read()
 }
 
+/// get current time in milliseconds
+pub fn get_time_ms() -> usize {
+    time::read() / (CLOCK_FREQ / MSEC_PER_SEC)
+}
+
 /// get current time in microseconds
 pub fn get_time_us() -> usize {
     time::read() / (CLOCK_FREQ / MICRO_PER_SEC)
@@ -21,3 +37,78 @@ pub fn get_time_us() -> usize {
 pub fn set_next_trigger() {
     set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
 }
+
+/// When the tick after the current one would come, in microseconds
+pub fn next_tick_us() -> usize {
+    get_time_us() + MICRO_PER_SEC / TICKS_PER_SEC
+}
+
+/// Arm the timer for the first sleeping thread to wake up, or for
+/// `deadline_us` if it comes earlier, instead of ticking periodically.
+/// Without either the timer is not armed at all.
+pub fn set_idle_trigger(deadline_us: Option<usize>) {
+    let timer_us = TIMERS.lock().peek().map(|timer| timer.expire_ms * 1000);
+    let next_us = [timer_us, deadline_us].iter().flatten().min().copied();
+    set_timer(next_us.map_or(usize::MAX, |us| us * (CLOCK_FREQ / MICRO_PER_SEC)));
+}
+
+/// A thread blocked until `expire_ms`
+pub struct TimerCondVar {
+    pub expire_ms: usize,
+    pub task: Arc<TaskControlBlock>,
+}
+
+impl PartialEq for TimerCondVar {
+    fn eq(&self, other: &Self) -> bool {
+        self.expire_ms == other.expire_ms
+    }
+}
+impl Eq for TimerCondVar {}
+impl PartialOrd for TimerCondVar {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TimerCondVar {
+    /// Reversed so that the BinaryHeap pops the earliest deadline first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.expire_ms.cmp(&self.expire_ms)
+    }
+}
+
+lazy_static! {
+    /// Min-heap of sleeping threads, ordered by deadline
+    static ref TIMERS: SpinNoIrqLock<BinaryHeap<TimerCondVar>> =
+        SpinNoIrqLock::new(BinaryHeap::<TimerCondVar>::new());
+}
+
+/// Wake `task` up once the clock reaches `expire_ms`
+pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
+    let mut timers = TIMERS.lock();
+    timers.push(TimerCondVar { expire_ms, task });
+}
+
+/// Forget about `task`, which will never be woken up by a timer
+pub fn remove_timer(task: Arc<TaskControlBlock>) {
+    let mut timers = TIMERS.lock();
+    let remaining: BinaryHeap<TimerCondVar> = timers
+        .drain()
+        .filter(|timer| !Arc::ptr_eq(&timer.task, &task))
+        .collect();
+    *timers = remaining;
+}
+
+/// Wake up every thread whose deadline has passed
+pub fn check_timer() {
+    let current_ms = get_time_ms();
+    let mut timers = TIMERS.lock();
+    while let Some(timer) = timers.peek() {
+        if timer.expire_ms <= current_ms {
+            wakeup_task(Arc::clone(&timer.task));
+            timers.pop();
+        } else {
+            break;
+        }
+    }
+}
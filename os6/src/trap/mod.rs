@@ -1,7 +1,9 @@
 //! Trap handling functionality
 //!
-//! For rCore, we have a single trap entry point, namely `__alltraps`. At
-//! initialization in [`init()`], we set the `stvec` CSR to point to it.
+//! For rCore, we have a single trap entry point from user space, namely
+//! `__alltraps`, which `stvec` points to while running user code. In kernel,
+//! `stvec` points to `__kerneltrap` instead, which lets the idle loop take
+//! interrupts and hands them to [`trap_from_kernel()`].
 //!
 //! All traps go through `__alltraps`, which is defined in `trap.S`. The
 //! assembly language code does just enough work restore the kernel space
@@ -14,16 +16,21 @@
 
 mod context;
 
-use crate::config::{TRAMPOLINE, TRAP_CONTEXT};
+use crate::config::TRAMPOLINE;
+use crate::fs::poll_console;
+use crate::mm::PageFaultKind;
 use crate::syscall::syscall;
 use crate::task::{
-    current_trap_cx, current_user_token, exit_current_and_run_next, suspend_current_and_run_next,
+    current_process_exited, current_trap_cx, current_trap_cx_user_va, current_user_token,
+    exit_current_and_run_next, handle_page_fault, handle_signals, reclaim_frames,
+    send_fault_signal, set_current_in_syscall, suspend_current_and_run_next, tick_current,
+    SignalFlags,
 };
-use crate::timer::set_next_trigger;
+use crate::timer::{check_timer, set_next_trigger};
 use riscv::register::{
     mtvec::TrapMode,
     scause::{self, Exception, Interrupt, Trap},
-    sie, stval, stvec,
+    sie, sip, stval, stvec,
 };
 
 core::arch::global_asm!(include_str!("trap.S"));
@@ -33,8 +40,11 @@ pub fn init() {
 }
 
 fn set_kernel_trap_entry() {
+    extern "C" {
+        fn __kerneltrap();
+    }
     unsafe {
-        stvec::write(trap_from_kernel as usize, TrapMode::Direct);
+        stvec::write(__kerneltrap as usize, TrapMode::Direct);
     }
 }
 
@@ -50,6 +60,13 @@ pub fn enable_timer_interrupt() {
     }
 }
 
+/// Let other harts wake this one up from idle with IPIs
+pub fn enable_software_interrupt() {
+    unsafe {
+        sie::set_ssoft();
+    }
+}
+
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
@@ -60,12 +77,27 @@ pub fn trap_handler() -> ! {
             // jump to next instruction anyway
             let mut cx = current_trap_cx();
             cx.sepc += 4;
+            // make room for what the syscall may allocate, e.g. on fork
+            reclaim_frames();
+            set_current_in_syscall(true);
             // get system call return value
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12], cx.x[13]]);
+            let result = syscall(
+                cx.x[17],
+                [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+            );
+            set_current_in_syscall(false);
             // cx is changed during sys_exec, so we have to call it again
             cx = current_trap_cx();
             cx.x[10] = result as usize;
         }
+        // the first access to a page of a lazy area or to a page swapped
+        // out, or a write to a page shared copy-on-write
+        Trap::Exception(Exception::StorePageFault)
+            if handle_page_fault(stval, PageFaultKind::Store) => {}
+        Trap::Exception(Exception::LoadPageFault)
+            if handle_page_fault(stval, PageFaultKind::Load) => {}
+        Trap::Exception(Exception::InstructionPageFault)
+            if handle_page_fault(stval, PageFaultKind::Fetch) => {}
         Trap::Exception(Exception::StoreFault)
         | Trap::Exception(Exception::StorePageFault)
         | Trap::Exception(Exception::InstructionFault)
@@ -73,22 +105,30 @@ pub fn trap_handler() -> ! {
         | Trap::Exception(Exception::LoadFault)
         | Trap::Exception(Exception::LoadPageFault) => {
             println!(
-                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.",
+                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, SIGSEGV.",
                 scause.cause(),
                 stval,
                 current_trap_cx().sepc,
             );
-            // page fault exit code
-            exit_current_and_run_next(-2);
+            send_fault_signal(SignalFlags::SIGSEGV);
         }
         Trap::Exception(Exception::IllegalInstruction) => {
-            println!("[kernel] IllegalInstruction in application, core dumped.");
-            // illegal instruction exit code
-            exit_current_and_run_next(-3);
+            println!("[kernel] IllegalInstruction in application, SIGILL.");
+            send_fault_signal(SignalFlags::SIGILL);
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
-            suspend_current_and_run_next();
+            check_timer();
+            poll_console();
+            if tick_current() {
+                suspend_current_and_run_next();
+            }
+        }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            // an IPI meant to wake us up from idle, which we have left
+            unsafe {
+                sip::clear_ssoft();
+            }
         }
         _ => {
             panic!(
@@ -98,13 +138,19 @@ pub fn trap_handler() -> ! {
             );
         }
     }
+    // the process is killed here if a fault above is not handled
+    handle_signals();
     trap_return();
 }
 
 #[no_mangle]
 pub fn trap_return() -> ! {
+    // the main thread may have exited on another hart in the meantime
+    if current_process_exited() {
+        exit_current_and_run_next(0);
+    }
     set_user_trap_entry();
-    let trap_cx_ptr = TRAP_CONTEXT;
+    let trap_cx_user_va = current_trap_cx_user_va();
     let user_satp = current_user_token();
     extern "C" {
         fn __alltraps();
@@ -116,7 +162,7 @@ pub fn trap_return() -> ! {
             "fence.i",
             "jr {restore_va}",
             restore_va = in(reg) restore_va,
-            in("a0") trap_cx_ptr,
+            in("a0") trap_cx_user_va,
             in("a1") user_satp,
             options(noreturn)
         );
@@ -124,8 +170,19 @@ pub fn trap_return() -> ! {
 }
 
 #[no_mangle]
-pub fn trap_from_kernel() -> ! {
-    panic!("a trap {:?} from kernel!", scause::read().cause());
+/// Handle interrupts taken in kernel, which only happens in the idle loop
+pub fn trap_from_kernel() {
+    match scause::read().cause() {
+        Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            set_next_trigger();
+            check_timer();
+            poll_console();
+        }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => unsafe {
+            sip::clear_ssoft();
+        },
+        cause => panic!("a trap {:?} from kernel!", cause),
+    }
 }
 
 pub use context::TrapContext;
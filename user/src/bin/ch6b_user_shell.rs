@@ -12,38 +12,47 @@ const DL: u8 = 0x7fu8;
 const BS: u8 = 0x08u8;
 
 use alloc::string::String;
+use alloc::vec;
 use user_lib::console::getchar;
-use user_lib::{exec, flush, fork, waitpid};
+use user_lib::job::{add_to_group, enter_group, take_console, Jobs};
+use user_lib::{exec, flush, fork};
 
 #[no_mangle]
 pub fn main() -> i32 {
     println!("Rust user shell");
+    take_console();
+    let mut jobs = Jobs::new(true);
     let mut line: String = String::new();
     print!(">> ");
     flush();
     loop {
         let c = getchar();
         match c {
+            // the read has been interrupted
+            0 => {}
             LF | CR => {
                 print!("\n");
-                if !line.is_empty() {
+                let (cmd, arg) = line.split_once(' ').unwrap_or((line.as_str(), ""));
+                let arg = arg.trim();
+                if !line.is_empty() && !jobs.run_builtin(cmd, arg) {
+                    let name = line.clone();
                     line.push('\0');
                     let pid = fork();
                     if pid == 0 {
-                        // child process
+                        // child process, in a new process group
+                        enter_group(0);
                         if exec(line.as_str(), &[0 as *const u8]) == -1 {
                             println!("Error when executing!");
                             return -4;
                         }
                         unreachable!();
                     } else {
-                        let mut exit_code: i32 = 0;
-                        let exit_pid = waitpid(pid as usize, &mut exit_code);
-                        assert_eq!(pid, exit_pid);
-                        println!("Shell: Process {} exited with code {}", pid, exit_code);
+                        let pgid = add_to_group(pid as usize, 0);
+                        jobs.wait_foreground(pgid, vec![pid as usize], name);
                     }
-                    line.clear();
                 }
+                line.clear();
+                jobs.check();
                 print!(">> ");
                 flush();
             }
@@ -12,22 +12,31 @@ const DL: u8 = 0x7fu8;
 const BS: u8 = 0x08u8;
 
 use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
 use user_lib::console::getchar;
-use user_lib::{close, dup, exec, flush, fork, open, waitpid, OpenFlags};
+use user_lib::job::{add_to_group, enter_group, take_console, Jobs};
+use user_lib::{close, dup2, exec, flush, fork, open, OpenFlags};
 
 #[no_mangle]
 pub fn main() -> i32 {
     println!("Rust user shell");
+    take_console();
+    let mut jobs = Jobs::new(true);
     let mut line: String = String::new();
     print!(">> ");
     flush();
     loop {
         let c = getchar();
         match c {
+            // the read has been interrupted
+            0 => {}
             LF | CR => {
                 println!("");
-                if !line.is_empty() {
+                let (cmd, arg) = line.split_once(' ').unwrap_or((line.as_str(), ""));
+                let arg = arg.trim();
+                if !line.is_empty() && !jobs.run_builtin(cmd, arg) {
+                    let name = line.clone();
                     let args: Vec<_> = line.as_str().split(' ').collect();
                     let mut args_copy: Vec<String> = args
                         .iter()
@@ -69,6 +78,8 @@ pub fn main() -> i32 {
                     args_addr.push(0 as *const u8);
                     let pid = fork();
                     if pid == 0 {
+                        // child process, in a new process group
+                        enter_group(0);
                         // input redirection
                         if !input.is_empty() {
                             let input_fd = open(input.as_str(), OpenFlags::RDONLY);
@@ -77,8 +88,7 @@ pub fn main() -> i32 {
                                 return -4;
                             }
                             let input_fd = input_fd as usize;
-                            close(0);
-                            assert_eq!(dup(input_fd), 0);
+                            assert_eq!(dup2(input_fd, 0), 0);
                             close(input_fd);
                         }
                         // output redirection
@@ -90,24 +100,21 @@ pub fn main() -> i32 {
                                 return -4;
                             }
                             let output_fd = output_fd as usize;
-                            close(1);
-                            assert_eq!(dup(output_fd), 1);
+                            assert_eq!(dup2(output_fd, 1), 1);
                             close(output_fd);
                         }
-                        // child process
                         if exec(args_copy[0].as_str(), args_addr.as_slice()) == -1 {
                             println!("Error when executing!");
                             return -4;
                         }
                         unreachable!();
                     } else {
-                        let mut exit_code: i32 = 0;
-                        let exit_pid = waitpid(pid as usize, &mut exit_code);
-                        assert_eq!(pid, exit_pid);
-                        println!("Shell: Process {} exited with code {}", pid, exit_code);
+                        let pgid = add_to_group(pid as usize, 0);
+                        jobs.wait_foreground(pgid, vec![pid as usize], name);
                     }
-                    line.clear();
                 }
+                line.clear();
+                jobs.check();
                 print!(">> ");
                 flush();
             }
@@ -16,7 +16,8 @@ const LINE_START: &str = ">> ";
 use alloc::string::String;
 use alloc::vec::Vec;
 use user_lib::console::getchar;
-use user_lib::{close, dup, exec, fork, open, pipe, waitpid, OpenFlags};
+use user_lib::job::{add_to_group, enter_group, take_console, Jobs};
+use user_lib::{close, dup, exec, fork, open, pipe, OpenFlags};
 
 #[derive(Debug)]
 struct ProcessArguments {
@@ -77,14 +78,20 @@ impl ProcessArguments {
 #[no_mangle]
 pub fn main() -> i32 {
     println!("Rust user shell");
+    take_console();
+    let mut jobs = Jobs::new(false);
     let mut line: String = String::new();
     print!("{}", LINE_START);
     loop {
         let c = getchar();
         match c {
+            // the read has been interrupted
+            0 => {}
             LF | CR => {
                 println!("");
-                if !line.is_empty() {
+                let (cmd, arg) = line.split_once(' ').unwrap_or((line.as_str(), ""));
+                let arg = arg.trim();
+                if !line.is_empty() && !jobs.run_builtin(cmd, arg) {
                     let splited: Vec<_> = line.as_str().split('|').collect();
                     let process_arguments_list: Vec<_> = splited
                         .iter()
@@ -121,9 +128,12 @@ pub fn main() -> i32 {
                             }
                         }
                         let mut children: Vec<_> = Vec::new();
+                        // the group of the pipeline, led by its first process
+                        let mut pgid = 0;
                         for (i, process_argument) in process_arguments_list.iter().enumerate() {
                             let pid = fork();
                             if pid == 0 {
+                                enter_group(pgid);
                                 let input = &process_argument.input;
                                 let output = &process_argument.output;
                                 let args_copy = &process_argument.args_copy;
@@ -179,22 +189,19 @@ pub fn main() -> i32 {
                                 }
                                 unreachable!();
                             } else {
-                                children.push(pid);
+                                pgid = add_to_group(pid as usize, pgid);
+                                children.push(pid as usize);
                             }
                         }
                         for pipe_fd in pipes_fd.iter() {
                             close(pipe_fd[0]);
                             close(pipe_fd[1]);
                         }
-                        let mut exit_code: i32 = 0;
-                        for pid in children.into_iter() {
-                            let exit_pid = waitpid(pid as usize, &mut exit_code);
-                            assert_eq!(pid, exit_pid);
-                            //println!("Shell: Process {} exited with code {}", pid, exit_code);
-                        }
+                        jobs.wait_foreground(pgid, children, line.clone());
                     }
-                    line.clear();
                 }
+                line.clear();
+                jobs.check();
                 print!("{}", LINE_START);
             }
             BS | DL => {
@@ -0,0 +1,214 @@
+//! Job control shared by the shells: every command line runs in a process
+//! group of its own, which gets the console while in the foreground, and
+//! can be stopped with ^Z, then resumed with the `fg` and `bg` built-ins.
+
+use super::{
+    getpid, kill, setpgid, sigaction, sys_waitpid, tcsetpgrp, waitpid_untraced, wifstopped,
+    wstopsig, SignalAction, WaitFlags, SIGCONT, SIGINT, SIGTSTP, SIG_IGN,
+};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// A command line which has been stopped or sent to the background, in a
+/// process group of its own led by its first process
+struct Job {
+    pgid: usize,
+    /// Processes of the command line which have not exited yet
+    pids: Vec<usize>,
+    /// The last process, whose exit code is that of the job
+    last: usize,
+    exit_code: i32,
+    name: String,
+    running: bool,
+}
+
+/// Set the handler of the signals coming from the console
+fn set_console_signals(action: &SignalAction) {
+    sigaction(SIGINT, Some(action), None);
+    sigaction(SIGTSTP, Some(action), None);
+}
+
+/// Take the console in a process group of our own, and leave ^C and ^Z to
+/// the commands
+pub fn take_console() {
+    setpgid(0, 0);
+    tcsetpgrp(getpid() as usize);
+    set_console_signals(&SignalAction {
+        handler: SIG_IGN,
+        ..Default::default()
+    });
+}
+
+/// Move a forked command into group `pgid`, 0 for a group of its own, with
+/// ^C and ^Z handled by default again
+pub fn enter_group(pgid: usize) {
+    setpgid(0, pgid);
+    set_console_signals(&SignalAction::default());
+}
+
+/// Put the forked command `pid` into group `pgid`, or a group of its own
+/// with `pgid` == 0, and return the group
+///
+/// The command does the same with [`enter_group`], as the next command of
+/// the line and `tcsetpgrp` need the group to exist whichever of us runs
+/// first.
+pub fn add_to_group(pid: usize, pgid: usize) -> usize {
+    let pgid = if pgid == 0 { pid } else { pgid };
+    setpgid(pid, pgid);
+    pgid
+}
+
+/// The jobs of a shell
+pub struct Jobs {
+    jobs: Vec<Job>,
+    /// Whether to tell the exit code of the commands run in the foreground
+    report_exit: bool,
+}
+
+impl Jobs {
+    pub fn new(report_exit: bool) -> Self {
+        Self {
+            jobs: Vec::new(),
+            report_exit,
+        }
+    }
+
+    /// Give the console to the command line `pids` in group `pgid`, the
+    /// last of which is the one of its output, and wait until each of them
+    /// exits or stops; a stopped one becomes a job
+    pub fn wait_foreground(&mut self, pgid: usize, pids: Vec<usize>, name: String) {
+        let last = *pids.last().unwrap();
+        self.wait_job(Job {
+            pgid,
+            pids,
+            last,
+            exit_code: 0,
+            name,
+            running: true,
+        });
+    }
+
+    /// Wait for `job` in the foreground, see [`Jobs::wait_foreground`]
+    fn wait_job(&mut self, mut job: Job) {
+        tcsetpgrp(job.pgid);
+        let last = job.last;
+        let mut last_exit_code = None;
+        let mut stop_signal = 0;
+        job.pids.retain(|&pid| {
+            let mut exit_code: i32 = 0;
+            let exit_pid = waitpid_untraced(pid as isize, &mut exit_code);
+            assert_eq!(pid as isize, exit_pid);
+            if wifstopped(exit_code) {
+                stop_signal = wstopsig(exit_code);
+                return true;
+            }
+            if pid == last {
+                last_exit_code = Some(exit_code);
+            }
+            false
+        });
+        if let Some(exit_code) = last_exit_code {
+            job.exit_code = exit_code;
+        }
+        tcsetpgrp(getpid() as usize);
+        if !job.pids.is_empty() {
+            job.running = false;
+            self.jobs.push(job);
+            println!(
+                "\n[{}] Stopped by signal {}: {}",
+                self.jobs.len(),
+                stop_signal,
+                self.jobs[self.jobs.len() - 1].name
+            );
+        } else if self.report_exit {
+            println!(
+                "Shell: Process {} exited with code {}",
+                job.last, job.exit_code
+            );
+        }
+    }
+
+    /// Report the jobs which have exited or stopped in the background
+    pub fn check(&mut self) {
+        let options = WaitFlags::WNOHANG | WaitFlags::WUNTRACED;
+        let mut i = 0;
+        while i < self.jobs.len() {
+            let job = &mut self.jobs[i];
+            let last = job.last;
+            let mut stopped = false;
+            let mut last_exit_code = None;
+            job.pids.retain(|&pid| {
+                let mut exit_code: i32 = 0;
+                if sys_waitpid(pid as isize, &mut exit_code as *mut _, options.bits()) < 0 {
+                    return true;
+                }
+                if wifstopped(exit_code) {
+                    stopped = true;
+                    return true;
+                }
+                if pid == last {
+                    last_exit_code = Some(exit_code);
+                }
+                false
+            });
+            if let Some(exit_code) = last_exit_code {
+                job.exit_code = exit_code;
+            }
+            if job.pids.is_empty() {
+                println!("[{}] Done with code {}: {}", i + 1, job.exit_code, job.name);
+                self.jobs.remove(i);
+            } else {
+                if stopped {
+                    job.running = false;
+                    println!("[{}] Stopped: {}", i + 1, job.name);
+                }
+                i += 1;
+            }
+        }
+    }
+
+    /// Run `cmd` with `arg` if it is one of the built-ins `jobs`, `fg` and
+    /// `bg`, and return whether it was
+    pub fn run_builtin(&mut self, cmd: &str, arg: &str) -> bool {
+        match cmd {
+            "jobs" => {
+                for (i, job) in self.jobs.iter().enumerate() {
+                    let state = if job.running { "Running" } else { "Stopped" };
+                    println!("[{}] {} {}", i + 1, state, job.name);
+                }
+            }
+            "fg" | "bg" => match self.parse_job(arg) {
+                Some(i) if cmd == "fg" => {
+                    let job = self.jobs.remove(i);
+                    kill(-(job.pgid as isize), SIGCONT);
+                    self.wait_job(job);
+                }
+                Some(i) => {
+                    let job = &mut self.jobs[i];
+                    job.running = true;
+                    kill(-(job.pgid as isize), SIGCONT);
+                    println!("[{}] {} &", i + 1, job.name);
+                }
+                None => {
+                    println!("{}: no such job", cmd);
+                }
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    /// Pick the job named by `arg`, "%n" or "n", or the last one if it is
+    /// empty
+    fn parse_job(&self, arg: &str) -> Option<usize> {
+        if arg.is_empty() {
+            return self.jobs.len().checked_sub(1);
+        }
+        let n: usize = arg.trim_start_matches('%').parse().ok()?;
+        if n >= 1 && n <= self.jobs.len() {
+            Some(n - 1)
+        } else {
+            None
+        }
+    }
+}
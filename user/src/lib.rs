@@ -5,6 +5,7 @@
 
 #[macro_use]
 pub mod console;
+pub mod job;
 mod lang_items;
 mod syscall;
 
@@ -15,15 +16,44 @@ extern crate bitflags;
 
 use alloc::vec::Vec;
 use buddy_system_allocator::LockedHeap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::NonNull;
 pub use console::{flush, STDIN, STDOUT};
 pub use syscall::*;
 
+/// Size of the heap taken with sbrk on start
 const USER_HEAP_SIZE: usize = 16384;
+const PAGE_SIZE: usize = 4096;
 
-static mut HEAP_SPACE: [u8; USER_HEAP_SIZE] = [0; USER_HEAP_SIZE];
+/// The buddy allocator over the heap, which extends the heap with sbrk
+/// whenever it runs out of memory
+struct GrowingHeap(LockedHeap);
+
+unsafe impl GlobalAlloc for GrowingHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.0.lock();
+        if let Ok(ptr) = heap.alloc(layout) {
+            return ptr.as_ptr();
+        }
+        // blocks are aligned to their power-of-two size, so twice a block
+        // holds one wherever the new memory starts
+        let size = layout.size().max(layout.align()).next_power_of_two() * 2;
+        let size = (size + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+        let start = sbrk(size as isize);
+        if start < 0 {
+            return core::ptr::null_mut();
+        }
+        heap.add_to_heap(start as usize, start as usize + size);
+        heap.alloc(layout)
+            .map_or(core::ptr::null_mut(), |ptr| ptr.as_ptr())
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout);
+    }
+}
 
 #[global_allocator]
-static HEAP: LockedHeap = LockedHeap::empty();
+static HEAP: GrowingHeap = GrowingHeap(LockedHeap::empty());
 
 #[alloc_error_handler]
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
@@ -48,9 +78,10 @@ fn clear_bss() {
 #[link_section = ".text.entry"]
 pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
     clear_bss();
+    let heap_start = sbrk(USER_HEAP_SIZE as isize);
+    assert!(heap_start >= 0, "cannot set up the heap");
     unsafe {
-        HEAP.lock()
-            .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
+        HEAP.0.lock().init(heap_start as usize, USER_HEAP_SIZE);
     }
     let mut v: Vec<&'static str> = Vec::new();
     for i in 0..argc {
@@ -85,6 +116,112 @@ bitflags! {
     }
 }
 
+bitflags! {
+    pub struct MmapFlags: usize {
+        const MAP_SHARED = 1 << 0;
+        const MAP_PRIVATE = 1 << 1;
+        const MAP_ANONYMOUS = 1 << 5;
+    }
+}
+
+bitflags! {
+    pub struct WaitFlags: usize {
+        const WNOHANG = 1 << 0;
+        const WUNTRACED = 1 << 1;
+    }
+}
+
+pub const SIGHUP: i32 = 1;
+pub const SIGINT: i32 = 2;
+pub const SIGQUIT: i32 = 3;
+pub const SIGILL: i32 = 4;
+pub const SIGTRAP: i32 = 5;
+pub const SIGABRT: i32 = 6;
+pub const SIGBUS: i32 = 7;
+pub const SIGFPE: i32 = 8;
+pub const SIGKILL: i32 = 9;
+pub const SIGUSR1: i32 = 10;
+pub const SIGSEGV: i32 = 11;
+pub const SIGUSR2: i32 = 12;
+pub const SIGPIPE: i32 = 13;
+pub const SIGALRM: i32 = 14;
+pub const SIGTERM: i32 = 15;
+pub const SIGSTKFLT: i32 = 16;
+pub const SIGCHLD: i32 = 17;
+pub const SIGCONT: i32 = 18;
+pub const SIGSTOP: i32 = 19;
+pub const SIGTSTP: i32 = 20;
+pub const SIGTTIN: i32 = 21;
+pub const SIGTTOU: i32 = 22;
+pub const SIGURG: i32 = 23;
+pub const SIGXCPU: i32 = 24;
+pub const SIGXFSZ: i32 = 25;
+pub const SIGVTALRM: i32 = 26;
+pub const SIGPROF: i32 = 27;
+pub const SIGWINCH: i32 = 28;
+pub const SIGIO: i32 = 29;
+pub const SIGPWR: i32 = 30;
+pub const SIGSYS: i32 = 31;
+
+/// Handler taking the default action of a signal
+pub const SIG_DFL: usize = 0;
+/// Handler ignoring a signal
+pub const SIG_IGN: usize = 1;
+
+bitflags! {
+    pub struct SignalFlags: u32 {
+        const SIGHUP    = 1 << 1;
+        const SIGINT    = 1 << 2;
+        const SIGQUIT   = 1 << 3;
+        const SIGILL    = 1 << 4;
+        const SIGTRAP   = 1 << 5;
+        const SIGABRT   = 1 << 6;
+        const SIGBUS    = 1 << 7;
+        const SIGFPE    = 1 << 8;
+        const SIGKILL   = 1 << 9;
+        const SIGUSR1   = 1 << 10;
+        const SIGSEGV   = 1 << 11;
+        const SIGUSR2   = 1 << 12;
+        const SIGPIPE   = 1 << 13;
+        const SIGALRM   = 1 << 14;
+        const SIGTERM   = 1 << 15;
+        const SIGSTKFLT = 1 << 16;
+        const SIGCHLD   = 1 << 17;
+        const SIGCONT   = 1 << 18;
+        const SIGSTOP   = 1 << 19;
+        const SIGTSTP   = 1 << 20;
+        const SIGTTIN   = 1 << 21;
+        const SIGTTOU   = 1 << 22;
+        const SIGURG    = 1 << 23;
+        const SIGXCPU   = 1 << 24;
+        const SIGXFSZ   = 1 << 25;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF   = 1 << 27;
+        const SIGWINCH  = 1 << 28;
+        const SIGIO     = 1 << 29;
+        const SIGPWR    = 1 << 30;
+        const SIGSYS    = 1 << 31;
+    }
+}
+
+/// How a signal is handled. A user handler gets the signal number as its
+/// argument, and has to finish with [`sigreturn`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SignalAction {
+    pub handler: usize,
+    pub mask: SignalFlags,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalFlags::empty(),
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Default)]
 pub struct TimeVal {
@@ -247,31 +384,78 @@ pub fn exec(path: &str, args: &[*const u8]) -> isize {
 pub fn set_priority(prio: isize) -> isize {
     sys_set_priority(prio)
 }
+pub fn set_realtime(period_ms: usize, runtime_ms: usize, deadline_ms: usize) -> isize {
+    sys_set_realtime(period_ms, runtime_ms, deadline_ms)
+}
+
+/// Send a signal to process `pid`, or to process group `-pid` if negative
+pub fn kill(pid: isize, signum: i32) -> isize {
+    sys_kill(pid, signum)
+}
+
+pub fn sigaction(
+    signum: i32,
+    action: Option<&SignalAction>,
+    old_action: Option<&mut SignalAction>,
+) -> isize {
+    sys_sigaction(
+        signum,
+        action.map_or(core::ptr::null(), |a| a),
+        old_action.map_or(core::ptr::null_mut(), |a| a),
+    )
+}
+
+pub fn sigprocmask(mask: u32) -> isize {
+    sys_sigprocmask(mask)
+}
+
+pub fn sigreturn() -> isize {
+    sys_sigreturn()
+}
 
 pub fn wait(exit_code: &mut i32) -> isize {
-    loop {
-        match sys_waitpid(-1, exit_code as *mut _) {
-            -2 => {
-                sys_yield();
-            }
-            n => {
-                return n;
-            }
-        }
-    }
+    sys_waitpid(-1, exit_code as *mut _, 0)
 }
 
 pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
-    loop {
-        match sys_waitpid(pid as isize, exit_code as *mut _) {
-            -2 => {
-                sys_yield();
-            }
-            n => {
-                return n;
-            }
-        }
-    }
+    sys_waitpid(pid as isize, exit_code as *mut _, 0)
+}
+
+/// Like [`waitpid`], but return -2 at once if the child has not exited
+pub fn waitpid_nohang(pid: isize, exit_code: &mut i32) -> isize {
+    sys_waitpid(pid, exit_code as *mut _, WaitFlags::WNOHANG.bits)
+}
+
+/// Like [`waitpid`], but also return when the child is stopped, which
+/// [`wifstopped`] tells from the exit code
+pub fn waitpid_untraced(pid: isize, exit_code: &mut i32) -> isize {
+    sys_waitpid(pid, exit_code as *mut _, WaitFlags::WUNTRACED.bits)
+}
+
+/// Whether an exit code from [`waitpid_untraced`] is for a stopped child
+pub fn wifstopped(exit_code: i32) -> bool {
+    exit_code & 0xff == 0x7f
+}
+
+/// The signal which stopped the child, for an exit code of a stopped child
+pub fn wstopsig(exit_code: i32) -> i32 {
+    (exit_code >> 8) & 0xff
+}
+
+pub fn setpgid(pid: usize, pgid: usize) -> isize {
+    sys_setpgid(pid, pgid)
+}
+
+pub fn getpgid(pid: usize) -> isize {
+    sys_getpgid(pid)
+}
+
+pub fn tcsetpgrp(pgid: usize) -> isize {
+    sys_tcsetpgrp(pgid)
+}
+
+pub fn tcgetpgrp() -> isize {
+    sys_tcgetpgrp()
 }
 
 pub fn sleep_blocking(sleep_ms: usize) {
@@ -284,8 +468,43 @@ pub fn sleep(period_ms: usize) {
         sys_yield();
     }
 }
+/// Set the program break to `addr`, and return the new break, or the
+/// current one if `addr` is 0; -1 on failure
+pub fn brk(addr: usize) -> isize {
+    sys_brk(addr)
+}
+
+/// Grow the heap by `increment` bytes, or shrink it if negative, and return
+/// the previous program break, which is where new memory starts; -1 on
+/// failure
+pub fn sbrk(increment: isize) -> isize {
+    let old_brk = sys_brk(0);
+    if old_brk < 0 || sys_brk((old_brk + increment) as usize) < 0 {
+        return -1;
+    }
+    old_brk
+}
+
 pub fn mmap(start: usize, len: usize, prot: usize) -> isize {
-    sys_mmap(start, len, prot)
+    let flags = MmapFlags::MAP_PRIVATE | MmapFlags::MAP_ANONYMOUS;
+    sys_mmap(start, len, prot, flags.bits(), usize::MAX, 0)
+}
+
+/// Map the file `fd` from `offset` on, which has to be page aligned
+pub fn mmap_file(
+    start: usize,
+    len: usize,
+    prot: usize,
+    flags: MmapFlags,
+    fd: usize,
+    offset: usize,
+) -> isize {
+    sys_mmap(start, len, prot, flags.bits(), fd, offset)
+}
+
+/// Write the dirty pages of shared file mappings back to their files
+pub fn msync(start: usize, len: usize) -> isize {
+    sys_msync(start, len, 0)
 }
 
 pub fn munmap(start: usize, len: usize) -> isize {
@@ -299,6 +518,12 @@ pub fn spawn(path: &str) -> isize {
 pub fn dup(fd: usize) -> isize {
     sys_dup(fd)
 }
+pub fn dup2(old_fd: usize, new_fd: usize) -> isize {
+    if old_fd == new_fd {
+        return new_fd as isize;
+    }
+    sys_dup3(old_fd, new_fd, 0)
+}
 pub fn pipe(pipe_fd: &mut [usize]) -> isize {
     sys_pipe(pipe_fd)
 }